@@ -9,13 +9,19 @@ fn main() -> Result<()> {
 
     client.login("username", "password")?;
 
-    client.list("/")?;
+    for entry in client.list("/")? {
+        println!("{:?}", entry);
+    }
 
     let mut file = File::open("Cargo.toml")?;
 
     client.put("Cargo.toml", &mut file)?;
 
-    client.list("/")?;
+    client.get("Cargo.toml", &mut std::io::stdout())?;
+
+    for entry in client.list("/")? {
+        println!("{:?}", entry);
+    }
 
     Ok(())
 }
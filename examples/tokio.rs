@@ -13,13 +13,19 @@ async fn main() -> Result<()> {
 
     client.login("username", "password").await?;
 
-    client.list("/").await?;
+    for entry in client.list("/").await? {
+        println!("{:?}", entry);
+    }
 
     let mut file = File::open("Cargo.toml").await?;
 
     client.put("Cargo.toml", &mut file).await?;
 
-    client.list("/").await?;
+    client.get("Cargo.toml", &mut tokio::io::stdout()).await?;
+
+    for entry in client.list("/").await? {
+        println!("{:?}", entry);
+    }
 
     Ok(())
 }
@@ -0,0 +1,27 @@
+#![no_main]
+
+use fteepee_core::{
+    parsers::{self, ListFormat},
+    DateTime,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|line: &str| {
+    let now = DateTime {
+        year: 2024,
+        month: 6,
+        day: 15,
+        ..DateTime::default()
+    };
+
+    let _ = parsers::parse_mlst_entry(line);
+    let _ = parsers::parse_mlsd_entry(line);
+    let _ = parsers::parse_unix_entry(line, now);
+    let _ = parsers::parse_dos_entry(line);
+    let _ = ListFormat::detect(line);
+    let _ = ListFormat::from_system(line);
+
+    for hint in [None, Some(ListFormat::Unix), Some(ListFormat::Dos)] {
+        let _ = parsers::parse_list_entry(line, hint, now);
+    }
+});
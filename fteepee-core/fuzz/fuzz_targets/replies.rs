@@ -0,0 +1,12 @@
+#![no_main]
+
+use fteepee_core::parsers;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parsers::parse_passive_mode(data);
+    let _ = parsers::parse_extended_passive_mode(data);
+    let _ = parsers::parse_size(data);
+    let _ = parsers::parse_pathname(data);
+    let _ = parsers::parse_features(data);
+});
@@ -0,0 +1,36 @@
+#![no_main]
+
+use fteepee_core::response::{Response, Status};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte picks the chunk size and initial output size, so replies are
+// split at every point and the output fills up part way through
+fuzz_target!(|data: &[u8]| {
+    let Some((size, input)) = data.split_first() else {
+        return;
+    };
+
+    let mut output = vec![0; usize::from(*size)];
+    let mut response = Response::new();
+
+    for mut chunk in input.chunks(usize::from(*size).max(1)) {
+        while !chunk.is_empty() {
+            match response.parse(chunk, &mut output) {
+                Ok(Status::Complete(n)) => {
+                    let _ = response.code();
+                    let _ = response.message(&output);
+
+                    response = Response::new();
+                    chunk = &chunk[n..];
+                }
+                // The output is full
+                Ok(Status::Partial(n)) if n < chunk.len() => {
+                    chunk = &chunk[n..];
+                    output.resize(output.len() * 2 + 1, 0);
+                }
+                Ok(Status::Partial(_)) => break,
+                Err(_) => return,
+            }
+        }
+    }
+});
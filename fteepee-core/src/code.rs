@@ -4,7 +4,7 @@ macro_rules! impl_codes {
 	(
         $(
             $(#[$docs:meta])*
-            ($code:expr, $name:ident);
+            ($code:expr, $name:ident, $text:expr);
         )+
     ) => {
 		impl Code {
@@ -12,121 +12,281 @@ macro_rules! impl_codes {
 				$(#[$docs])*
 					pub const $name: Code = Code(unsafe { core::num::NonZeroU16::new_unchecked($code)});
 			)*
-		}
 
-		impl core::convert::TryFrom<[u8; 3]> for Code {
-			type Error = crate::Error;
-
-			fn try_from(bytes: [u8; 3]) -> $crate::Result<Self> {
-				let b = &bytes[..];
-				$(
-					if stringify!($code).as_bytes() == b {
-						return Ok(Code::$name);
-					}
-				)*
-				Err(crate::Error::InvalidCode(bytes))
+			/// The text the RFCs give for this code, if it is a known one.
+			pub fn text(self) -> Option<&'static str> {
+				match self.0.get() {
+					$($code => Some($text),)*
+					_ => None,
+				}
 			}
 		}
 	};
 }
 
+/// A reply code, any three digits where the first is `1` to `6` and the
+/// second is `0` to `5`. Named constants are provided for the codes defined
+/// by [RFC 959](https://www.rfc-editor.org/rfc/rfc959#section-4.2),
+/// [RFC 2228](https://www.rfc-editor.org/rfc/rfc2228#section-4) and
+/// [RFC 2428](https://www.rfc-editor.org/rfc/rfc2428).
 #[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Code(core::num::NonZeroU16);
 
+impl Code {
+    /// The code as a number, e.g. `550`.
+    pub fn as_u16(self) -> u16 {
+        self.0.get()
+    }
+
+    /// 1yz, the command was accepted and another reply will follow.
+    pub fn is_preliminary(self) -> bool {
+        self.class() == 1
+    }
+
+    /// 2yz, the command succeeded.
+    pub fn is_completion(self) -> bool {
+        self.class() == 2
+    }
+
+    /// 3yz, the command was accepted but another command is needed.
+    pub fn is_intermediate(self) -> bool {
+        self.class() == 3
+    }
+
+    /// 4yz, the command failed but may succeed if tried again.
+    pub fn is_transient_failure(self) -> bool {
+        self.class() == 4
+    }
+
+    /// 5yz, the command failed and will keep failing.
+    pub fn is_permanent_failure(self) -> bool {
+        self.class() == 5
+    }
+
+    /// 6yz, the reply wraps another one protected by a security mechanism,
+    /// see [RFC 2228](https://www.rfc-editor.org/rfc/rfc2228#page-9).
+    pub fn is_protected(self) -> bool {
+        self.class() == 6
+    }
+
+    fn class(self) -> u16 {
+        self.0.get() / 100
+    }
+}
+
+impl core::convert::TryFrom<[u8; 3]> for Code {
+    type Error = crate::Error;
+
+    fn try_from(bytes: [u8; 3]) -> crate::Result<Self> {
+        match bytes {
+            [first @ b'1'..=b'6', second @ b'0'..=b'5', third @ b'0'..=b'9'] => {
+                let code = u16::from(first - b'0') * 100
+                    + u16::from(second - b'0') * 10
+                    + u16::from(third - b'0');
+
+                // The first digit is at least 1
+                Ok(Code(unsafe { core::num::NonZeroU16::new_unchecked(code) }))
+            }
+            _ => Err(crate::Error::InvalidCode(bytes)),
+        }
+    }
+}
+
 impl fmt::Debug for Code {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(&self.0, f)
     }
 }
 
+/// Formats the code followed by its standard text when it has one, e.g.
+/// `550 Requested action not taken. File unavailable.`
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.text() {
+            Some(text) => write!(f, "{} {}", self.0, text),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
 impl_codes! {
     /// 110 - Restart marker reply.
-    (110, RESTART_MARKER_REPLY);
+    (110, RESTART_MARKER_REPLY, "Restart marker reply.");
     /// 120 - Service ready in nnn minutes.
-    (120, SERVICE_READY);
+    (120, SERVICE_READY, "Service ready in nnn minutes.");
     /// 125 - Data connection already open; transfer starting.
-    (125, DATA_CONNECTION_ALREADY_OPEN);
+    (125, DATA_CONNECTION_ALREADY_OPEN, "Data connection already open; transfer starting.");
     /// 150 - File status okay; about to open data connection.
-    (150, OPENING_DATA_CONNECTION);
+    (150, OPENING_DATA_CONNECTION, "File status okay; about to open data connection.");
 
     /// 200 - Command okay
-    (200, COMMAND_OKAY);
+    (200, COMMAND_OKAY, "Command okay.");
+    /// 202 - Command not implemented, superfluous at this site.
+    (202, COMMAND_SUPERFLUOUS, "Command not implemented, superfluous at this site.");
     /// 211 - System status, or system help reply.
-    (211, SYSTEM_STATUS);
+    (211, SYSTEM_STATUS, "System status, or system help reply.");
     /// 212 - Directory status.
-    (212, DIRECTORY_STATUS);
+    (212, DIRECTORY_STATUS, "Directory status.");
     /// 213 - File status.
-    (213, FILE_STATUS);
+    (213, FILE_STATUS, "File status.");
     /// 214 - Help message.
-    (214, HELP);
+    (214, HELP, "Help message.");
     /// 215 - NAME system type.
     /// Where NAME is an official system name from the list in the Assigned Numbers document.
-    (215, SYSTEM_TYPE);
+    (215, SYSTEM_TYPE, "NAME system type.");
     /// 220 - Service ready for new user.
-    (220, READY);
+    (220, READY, "Service ready for new user.");
     /// 221 - Service closing control connection. Logged out if appropriate.
-    (221, CLOSING_CONTROL_CONNECTION);
+    (221, CLOSING_CONTROL_CONNECTION, "Service closing control connection.");
     /// Data connection open; no transfer in progress.
-    (225, DATA_CONNECTION_OPEN);
+    (225, DATA_CONNECTION_OPEN, "Data connection open; no transfer in progress.");
     /// 226 - Closing data connection. Requested file action successful (for
     /// example, file transfer or file abort).
-    (226, CLOSING_DATA_CONNECTION);
+    (226, CLOSING_DATA_CONNECTION, "Closing data connection.");
     /// 227 - Entering Passive Mode (h1,h2,h3,h4,p1,p2).
-    (227, ENTERING_PASSIVE_MODE);
+    (227, ENTERING_PASSIVE_MODE, "Entering Passive Mode.");
+    /// 229 - Entering Extended Passive Mode (|||port|).
+    (229, ENTERING_EXTENDED_PASSIVE_MODE, "Entering Extended Passive Mode.");
     /// 230 - User logged in, proceed.
-    (230, LOGGED_IN);
+    (230, LOGGED_IN, "User logged in, proceed.");
+    /// 232 - User logged in, authorized by security data exchange.
+    (232, LOGGED_IN_AUTHORIZED, "User logged in, authorized by security data exchange.");
+    /// 234 - Security data exchange complete.
+    (234, SECURITY_DATA_EXCHANGE_COMPLETE, "Security data exchange complete.");
+    /// 235 - Security data exchange completed successfully.
+    (235, SECURITY_DATA_EXCHANGE_SUCCESSFUL, "Security data exchange completed successfully.");
     /// 250 - Requested file action okay, completed.
-    (250, REQUESTED_FILE_ACTION_OKAY);
+    (250, REQUESTED_FILE_ACTION_OKAY, "Requested file action okay, completed.");
     /// 257 - "PATHNAME" created.
-    (257, CREATED);
+    (257, CREATED, "\"PATHNAME\" created.");
 
-    /// 330 - User name okay, need password.
-    (331, PASSWORD_REQUIRED);
+    /// 331 - User name okay, need password.
+    (331, PASSWORD_REQUIRED, "User name okay, need password.");
     /// 332 - Need account for login.
-    (332, NEED_ACCOUNT_FOR_LOGIN);
+    (332, NEED_ACCOUNT_FOR_LOGIN, "Need account for login.");
+    /// 334 - Requested security mechanism is ok, security data follows.
+    (334, SECURITY_MECHANISM_ACCEPTED, "Requested security mechanism is ok.");
+    /// 335 - Security data is acceptable, more is required.
+    (335, SECURITY_DATA_ACCEPTABLE, "Security data is acceptable, more is required.");
+    /// 336 - Username okay, need password. Challenge is "....".
+    (336, PASSWORD_CHALLENGE, "Username okay, need password.");
     /// 350 - Requested file action pending further information.
-    (350, REQUESTED_FILE_ACTION_PENDING_FURTHER_INFORMATION);
+    (350, REQUESTED_FILE_ACTION_PENDING_FURTHER_INFORMATION, "Requested file action pending further information.");
 
     /// 421 - Service not available, closing control connection.
     /// This may be a reply to any command if the service knows it must shut down.
-    (421, SERVICE_NOT_AVAILABLE);
+    (421, SERVICE_NOT_AVAILABLE, "Service not available, closing control connection.");
     /// 425 - Can't open data connection.
-    (425, CANT_OPEN_DATA_CONNECTION);
+    (425, CANT_OPEN_DATA_CONNECTION, "Can't open data connection.");
     /// 426 - Connection closed; transfer aborted.
-    (426, CONNECTION_CLOSED);
+    (426, CONNECTION_CLOSED, "Connection closed; transfer aborted.");
+    /// 431 - Need some unavailable resource to process security.
+    (431, SECURITY_RESOURCE_UNAVAILABLE, "Need some unavailable resource to process security.");
     /// 450 - Requested file action not taken.
     /// File unavailable (e.g., file busy).
-    (450, FILE_ACTION_UNAVAILABLE);
+    (450, FILE_ACTION_UNAVAILABLE, "Requested file action not taken. File unavailable.");
     /// 451 - Requested action aborted: local error in processing.
-    (451, LOCAL_ERROR_IN_PROCESSING);
+    (451, LOCAL_ERROR_IN_PROCESSING, "Requested action aborted: local error in processing.");
     /// 452 - Requested action not taken.
     /// Insufficient storage space in system.
-    (452, INSUFFICIENT_STORAGE_SPACE);
+    (452, INSUFFICIENT_STORAGE_SPACE, "Requested action not taken. Insufficient storage space in system.");
 
     /// 500 - Syntax error, command unrecognized.
     /// This may include errors such as command line too long.
-    (500, UNRECOGNIZED_COMMAND);
+    (500, UNRECOGNIZED_COMMAND, "Syntax error, command unrecognized.");
     /// 501 - Syntax error in parameters or arguments.
-    (501, UNRECOGNIZED_ARGUMENTS);
+    (501, UNRECOGNIZED_ARGUMENTS, "Syntax error in parameters or arguments.");
     /// 502 - Command not implemented.
-    (502, NOT_IMPLEMENTED);
+    (502, NOT_IMPLEMENTED, "Command not implemented.");
     /// 503 - Bad sequence of commands.
-    (503, BAD_SEQUENCE);
+    (503, BAD_SEQUENCE, "Bad sequence of commands.");
     /// 504 - Command not implemented for that parameter.
-    (504, NOT_IMPLEMENTED_FOR_PARAMETER);
+    (504, NOT_IMPLEMENTED_FOR_PARAMETER, "Command not implemented for that parameter.");
+    /// 522 - Network protocol not supported, the protocols that are follow
+    /// in parentheses, e.g. `(1,2)`.
+    (522, NETWORK_PROTOCOL_NOT_SUPPORTED, "Network protocol not supported.");
     /// 530 - Not logged in.
-    (530, NOT_LOGGED_IN);
+    (530, NOT_LOGGED_IN, "Not logged in.");
     /// 532 - Need account for storing files.
-    (532, NEED_ACCOUNT_FOR_STORING_FILES);
+    (532, NEED_ACCOUNT_FOR_STORING_FILES, "Need account for storing files.");
+    /// 533 - Command protection level denied for policy reasons.
+    (533, COMMAND_PROTECTION_DENIED, "Command protection level denied for policy reasons.");
+    /// 534 - Request denied for policy reasons.
+    (534, REQUEST_DENIED, "Request denied for policy reasons.");
+    /// 535 - Failed security check (hash, sequence, etc).
+    (535, FAILED_SECURITY_CHECK, "Failed security check.");
+    /// 536 - Requested PROT level not supported by mechanism.
+    (536, PROTECTION_LEVEL_NOT_SUPPORTED, "Requested PROT level not supported by mechanism.");
+    /// 537 - Command protection level not supported by security mechanism.
+    (537, COMMAND_PROTECTION_NOT_SUPPORTED, "Command protection level not supported by security mechanism.");
     /// 550 - Requested action not taken.
     /// File unavailable (e.g., file not found, no access).
-    (550, FILE_UNAVAILABLE);
+    (550, FILE_UNAVAILABLE, "Requested action not taken. File unavailable.");
     /// 551 - Requested action aborted: page type unknown.
-    (551, PAGE_TYPE_UNKNOWN);
+    (551, PAGE_TYPE_UNKNOWN, "Requested action aborted: page type unknown.");
     /// 552 - Requested file action aborted.
     /// Exceeded storage allocation (for current directory or dataset).
-    (552, EXCEEDED_STORAGE_ALLOCATION);
+    (552, EXCEEDED_STORAGE_ALLOCATION, "Requested file action aborted. Exceeded storage allocation.");
     /// 553 - Requested action not taken.
     /// File name not allowed.
-    (553, FILE_NAME_NOT_ALLOWED);
+    (553, FILE_NAME_NOT_ALLOWED, "Requested action not taken. File name not allowed.");
+
+    /// 631 - Integrity protected reply.
+    (631, INTEGRITY_PROTECTED_REPLY, "Integrity protected reply.");
+    /// 632 - Confidentiality and integrity protected reply.
+    (632, CONFIDENTIALITY_AND_INTEGRITY_PROTECTED_REPLY, "Confidentiality and integrity protected reply.");
+    /// 633 - Confidentiality protected reply.
+    (633, CONFIDENTIALITY_PROTECTED_REPLY, "Confidentiality protected reply.");
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::TryFrom;
+
+    use super::Code;
+    use crate::Error;
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(Code::try_from(*b"550").unwrap(), Code::FILE_UNAVAILABLE);
+        assert_eq!(
+            Code::try_from(*b"631").unwrap(),
+            Code::INTEGRITY_PROTECTED_REPLY
+        );
+
+        // Codes without a name are still valid
+        assert_eq!(Code::try_from(*b"259").unwrap().as_u16(), 259);
+        assert_eq!(Code::try_from(*b"100").unwrap().as_u16(), 100);
+
+        for invalid in [*b"000", *b"099", *b"700", *b"560", *b"2a0", *b" 20"] {
+            assert!(
+                matches!(Code::try_from(invalid), Err(Error::InvalidCode(_))),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(Code::OPENING_DATA_CONNECTION.is_preliminary());
+        assert!(Code::LOGGED_IN.is_completion());
+        assert!(Code::PASSWORD_REQUIRED.is_intermediate());
+        assert!(Code::SERVICE_NOT_AVAILABLE.is_transient_failure());
+        assert!(Code::FILE_UNAVAILABLE.is_permanent_failure());
+        assert!(Code::CONFIDENTIALITY_PROTECTED_REPLY.is_protected());
+
+        assert!(!Code::FILE_UNAVAILABLE.is_transient_failure());
+        assert!(!Code::LOGGED_IN.is_intermediate());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Code::FILE_UNAVAILABLE.to_string(),
+            "550 Requested action not taken. File unavailable."
+        );
+        assert_eq!(Code::try_from(*b"259").unwrap().to_string(), "259");
+    }
 }
@@ -1,3 +1,10 @@
+use core::{
+    fmt,
+    net::{SocketAddr, SocketAddrV4},
+};
+
+use crate::{Error, Result};
+
 const TELNET_END_OF_LINE: &[u8] = b"\r\n";
 const CMD: usize = 4;
 const SPACE: usize = 1;
@@ -8,6 +15,17 @@ fn write(buf: &mut [u8], data: &[u8], n: usize) -> usize {
     data.len()
 }
 
+/// Fails with [`Error::InvalidArgument`] if `arg` contains CR, LF or NUL. A
+/// line break would end the command early and the server would run whatever
+/// follows as a command of its own.
+pub fn check_arg(arg: &[u8]) -> Result<()> {
+    if arg.iter().any(|byte| matches!(byte, b'\r' | b'\n' | b'\0')) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(())
+}
+
 macro_rules! impl_commands {
 	(
 		$(
@@ -28,17 +46,46 @@ macro_rules! impl_commands {
 				}
 
 				fn size(&self) -> usize {
-					CMD + $(SPACE + self.$field.len() +)* EOL
+					$cmd.len() + $(SPACE + self.$field.len() +)* EOL
+				}
+
+				fn check(&self) -> Result<()> {
+					$(check_arg(self.$field.as_bytes())?;)*
+					Ok(())
 				}
 			}
 		)*
 	}
 }
 
+/// Hides the arguments of commands that carry credentials, so that they can
+/// be logged.
+#[cfg(feature = "std")]
+pub fn redact(command: &[u8]) -> std::borrow::Cow<'_, [u8]> {
+    const SENSITIVE: [&[u8]; 2] = [b"PASS", b"ACCT"];
+
+    match command.get(..CMD + SPACE) {
+        Some(prefix)
+            if SENSITIVE
+                .iter()
+                .any(|cmd| prefix[..CMD].eq_ignore_ascii_case(cmd)) =>
+        {
+            [prefix, b"****", TELNET_END_OF_LINE].concat().into()
+        }
+        _ => command.into(),
+    }
+}
+
 pub trait Command {
     fn encode(&self, buf: &mut [u8]);
 
     fn size(&self) -> usize;
+
+    /// Checks the arguments with [`check_arg`], which should be done before
+    /// the command is encoded.
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub enum Type {
@@ -48,6 +95,20 @@ pub enum Type {
     Local,
 }
 
+/// Transfer mode, see [RFC 959](https://www.rfc-editor.org/rfc/rfc959#section-3.4).
+pub enum Mode {
+    Stream,
+    Block,
+    Compressed,
+}
+
+/// File structure, see [RFC 959](https://www.rfc-editor.org/rfc/rfc959#section-3.1.2).
+pub enum Stru {
+    File,
+    Record,
+    Page,
+}
+
 impl_commands! {
     (User<'_>, b"USER", user);
     (Pass<'_>, b"PASS", pass);
@@ -57,6 +118,64 @@ impl_commands! {
     (Syst, b"SYST");
     (Feat, b"FEAT");
     (Stor<'_>, b"STOR", path);
+    (Retr<'_>, b"RETR", path);
+    (Epsv, b"EPSV");
+    (Eprt, b"EPRT", addr);
+    (Port, b"PORT", addr);
+    (Auth<'_>, b"AUTH", mechanism);
+    (Pbsz, b"PBSZ", size);
+    (Rest, b"REST", offset);
+    (Size<'_>, b"SIZE", path);
+    (Cwd<'_>, b"CWD", path);
+    (Cdup, b"CDUP");
+    (Pwd, b"PWD");
+    (Mkd<'_>, b"MKD", path);
+    (Rmd<'_>, b"RMD", path);
+    (Dele<'_>, b"DELE", path);
+    (Rnfr<'_>, b"RNFR", path);
+    (Rnto<'_>, b"RNTO", path);
+    (Noop, b"NOOP");
+    (Quit, b"QUIT");
+    (Abor, b"ABOR");
+    (Rein, b"REIN");
+    (Acct<'_>, b"ACCT", account);
+    (Smnt<'_>, b"SMNT", path);
+    (Allo, b"ALLO", size);
+    (Appe<'_>, b"APPE", path);
+    (Stou, b"STOU");
+    (Nlst<'_>, b"NLST", path);
+    (Site<'_>, b"SITE", command);
+}
+
+/// Data channel protection level, see
+/// [RFC 2228](https://www.rfc-editor.org/rfc/rfc2228#section-3).
+pub enum Prot {
+    Clear,
+    Safe,
+    Confidential,
+    Private,
+}
+
+impl Command for Prot {
+    fn encode(&self, buf: &mut [u8]) {
+        let level = match *self {
+            Prot::Clear => b"C",
+            Prot::Safe => b"S",
+            Prot::Confidential => b"E",
+            Prot::Private => b"P",
+        };
+
+        let mut n = 0usize;
+
+        n += write(buf, &b"PROT"[..], n);
+        n += write(buf, &b" "[..], n);
+        n += write(buf, &level[..], n);
+        write(buf, TELNET_END_OF_LINE, n);
+    }
+
+    fn size(&self) -> usize {
+        CMD + SPACE + 1 + EOL
+    }
 }
 
 impl Command for Type {
@@ -81,6 +200,85 @@ impl Command for Type {
     }
 }
 
+impl Command for Mode {
+    fn encode(&self, buf: &mut [u8]) {
+        let mode = match *self {
+            Mode::Stream => b"S",
+            Mode::Block => b"B",
+            Mode::Compressed => b"C",
+        };
+
+        let mut n = 0usize;
+
+        n += write(buf, &b"MODE"[..], n);
+        n += write(buf, &b" "[..], n);
+        n += write(buf, &mode[..], n);
+        write(buf, TELNET_END_OF_LINE, n);
+    }
+
+    fn size(&self) -> usize {
+        CMD + SPACE + 1 + EOL
+    }
+}
+
+impl Command for Stru {
+    fn encode(&self, buf: &mut [u8]) {
+        let structure = match *self {
+            Stru::File => b"F",
+            Stru::Record => b"R",
+            Stru::Page => b"P",
+        };
+
+        let mut n = 0usize;
+
+        n += write(buf, &b"STRU"[..], n);
+        n += write(buf, &b" "[..], n);
+        n += write(buf, &structure[..], n);
+        write(buf, TELNET_END_OF_LINE, n);
+    }
+
+    fn size(&self) -> usize {
+        CMD + SPACE + 1 + EOL
+    }
+}
+
+/// Commands whose argument may be left out, e.g. `STAT` and `STAT path`.
+macro_rules! impl_optional_commands {
+	(
+		$(
+			($t:ty, $cmd:literal, $field:ident);
+		)+
+	) => {
+		$(
+			impl Command for $t {
+				fn encode(&self, buf: &mut [u8]) {
+					let mut n = 0usize;
+
+					n += write(buf, &$cmd[..], n);
+					if let Some(arg) = self.$field {
+						n += write(buf, b" ", n);
+						n += write(buf, arg.as_bytes(), n);
+					}
+					write(buf, TELNET_END_OF_LINE, n);
+				}
+
+				fn size(&self) -> usize {
+					$cmd.len() + self.$field.map_or(0, |arg| SPACE + arg.len()) + EOL
+				}
+
+				fn check(&self) -> Result<()> {
+					self.$field.map_or(Ok(()), |arg| check_arg(arg.as_bytes()))
+				}
+			}
+		)*
+	}
+}
+
+impl_optional_commands! {
+    (Stat<'_>, b"STAT", path);
+    (Help<'_>, b"HELP", command);
+}
+
 pub struct User<'a> {
     user: &'a str,
 }
@@ -140,10 +338,329 @@ impl<'a> Stor<'a> {
     }
 }
 
+pub struct Retr<'a> {
+    path: &'a str,
+}
+
+impl<'a> Retr<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+#[derive(Default)]
+pub struct Epsv;
+
+pub struct Eprt {
+    addr: Arg,
+}
+
+impl Eprt {
+    pub fn new(addr: SocketAddr) -> Self {
+        let protocol = match addr {
+            SocketAddr::V4(_) => 1,
+            SocketAddr::V6(_) => 2,
+        };
+
+        Self {
+            addr: Arg::format(format_args!("|{}|{}|{}|", protocol, addr.ip(), addr.port())),
+        }
+    }
+}
+
+pub struct Port {
+    addr: Arg,
+}
+
+impl Port {
+    pub fn new(addr: SocketAddrV4) -> Self {
+        let [h1, h2, h3, h4] = addr.ip().octets();
+        let [p1, p2] = addr.port().to_be_bytes();
+
+        Self {
+            addr: Arg::format(format_args!("{},{},{},{},{},{}", h1, h2, h3, h4, p1, p2)),
+        }
+    }
+}
+
+pub struct Auth<'a> {
+    mechanism: &'a str,
+}
+
+impl<'a> Auth<'a> {
+    pub fn new(mechanism: &'a str) -> Self {
+        Self { mechanism }
+    }
+}
+
+pub struct Pbsz {
+    size: Arg,
+}
+
+impl Pbsz {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size: Arg::format(format_args!("{}", size)),
+        }
+    }
+}
+
+/// Restarts the next transfer at `offset` bytes, see
+/// [RFC 3659](https://www.rfc-editor.org/rfc/rfc3659#section-5).
+pub struct Rest {
+    offset: Arg,
+}
+
+impl Rest {
+    pub fn new(offset: u64) -> Self {
+        Self {
+            offset: Arg::format(format_args!("{}", offset)),
+        }
+    }
+}
+
+pub struct Size<'a> {
+    path: &'a str,
+}
+
+impl<'a> Size<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Cwd<'a> {
+    path: &'a str,
+}
+
+impl<'a> Cwd<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+#[derive(Default)]
+pub struct Cdup;
+
+#[derive(Default)]
+pub struct Pwd;
+
+pub struct Mkd<'a> {
+    path: &'a str,
+}
+
+impl<'a> Mkd<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Rmd<'a> {
+    path: &'a str,
+}
+
+impl<'a> Rmd<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Dele<'a> {
+    path: &'a str,
+}
+
+impl<'a> Dele<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Rnfr<'a> {
+    path: &'a str,
+}
+
+impl<'a> Rnfr<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Rnto<'a> {
+    path: &'a str,
+}
+
+impl<'a> Rnto<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+#[derive(Default)]
+pub struct Noop;
+
+#[derive(Default)]
+pub struct Quit;
+
+#[derive(Default)]
+pub struct Abor;
+
+#[derive(Default)]
+pub struct Rein;
+
+pub struct Acct<'a> {
+    account: &'a str,
+}
+
+impl<'a> Acct<'a> {
+    pub fn new(account: &'a str) -> Self {
+        Self { account }
+    }
+}
+
+pub struct Smnt<'a> {
+    path: &'a str,
+}
+
+impl<'a> Smnt<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+/// Reserves storage for a file about to be uploaded.
+pub struct Allo {
+    size: Arg,
+}
+
+impl Allo {
+    pub fn new(size: u64) -> Self {
+        Self {
+            size: Arg::format(format_args!("{}", size)),
+        }
+    }
+
+    /// For files sent with record or page structure, `record_size` is the
+    /// largest record or page.
+    pub fn with_record_size(size: u64, record_size: u64) -> Self {
+        Self {
+            size: Arg::format(format_args!("{} R {}", size, record_size)),
+        }
+    }
+}
+
+pub struct Appe<'a> {
+    path: &'a str,
+}
+
+impl<'a> Appe<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+#[derive(Default)]
+pub struct Stou;
+
+pub struct Nlst<'a> {
+    path: &'a str,
+}
+
+impl<'a> Nlst<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path }
+    }
+}
+
+pub struct Site<'a> {
+    command: &'a str,
+}
+
+impl<'a> Site<'a> {
+    pub fn new(command: &'a str) -> Self {
+        Self { command }
+    }
+}
+
+/// Without a path the server replies with its status, with a path it replies
+/// like LIST but over the control connection.
+#[derive(Default)]
+pub struct Stat<'a> {
+    path: Option<&'a str>,
+}
+
+impl<'a> Stat<'a> {
+    pub fn new(path: Option<&'a str>) -> Self {
+        Self { path }
+    }
+}
+
+#[derive(Default)]
+pub struct Help<'a> {
+    command: Option<&'a str>,
+}
+
+impl<'a> Help<'a> {
+    pub fn new(command: Option<&'a str>) -> Self {
+        Self { command }
+    }
+}
+
+/// An argument formatted into a fixed size buffer, large enough for any of the
+/// numeric or address arguments used by commands.
+struct Arg {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl Arg {
+    fn format(args: fmt::Arguments<'_>) -> Self {
+        let mut arg = Self {
+            buf: [0; 64],
+            len: 0,
+        };
+
+        fmt::Write::write_fmt(&mut arg, args).expect("argument exceeds buffer");
+
+        arg
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl fmt::Write for Arg {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn redacted_command() {
+        assert_eq!(&*redact(b"PASS secret\r\n"), b"PASS ****\r\n");
+        assert_eq!(&*redact(b"acct secret\r\n"), b"acct ****\r\n");
+        assert_eq!(&*redact(b"USER name\r\n"), b"USER name\r\n");
+        assert_eq!(&*redact(b"PWD\r\n"), b"PWD\r\n");
+    }
+
     #[test]
     fn enum_command() {
         let mut output: [u8; 64] = [0; 64];
@@ -176,4 +693,124 @@ mod tests {
 
         assert_eq!(output[..cmd.size()], b"USER foo\r\n"[..]);
     }
+
+    #[test]
+    fn formatted_argument_command() {
+        let mut output: [u8; 64] = [0; 64];
+
+        let cmd = Eprt::new(SocketAddr::from(([132, 235, 1, 2], 6275)));
+
+        cmd.encode(&mut output);
+
+        assert_eq!(output[..cmd.size()], b"EPRT |1|132.235.1.2|6275|\r\n"[..]);
+
+        let cmd = Eprt::new("[1080::8:800:200c:417a]:5282".parse().unwrap());
+
+        cmd.encode(&mut output);
+
+        assert_eq!(
+            output[..cmd.size()],
+            b"EPRT |2|1080::8:800:200c:417a|5282|\r\n"[..]
+        );
+
+        let cmd = Port::new(SocketAddrV4::new([192, 168, 0, 10].into(), 30001));
+
+        cmd.encode(&mut output);
+
+        assert_eq!(output[..cmd.size()], b"PORT 192,168,0,10,117,49\r\n"[..]);
+
+        let cmd = Pbsz::new(0);
+
+        cmd.encode(&mut output);
+
+        assert_eq!(output[..cmd.size()], b"PBSZ 0\r\n"[..]);
+
+        let cmd = Rest::new(u64::MAX);
+
+        cmd.encode(&mut output);
+
+        assert_eq!(output[..cmd.size()], b"REST 18446744073709551615\r\n"[..]);
+    }
+
+    fn assert_encodes(cmd: &dyn Command, expected: &[u8]) {
+        let mut output: [u8; 64] = [0; 64];
+
+        cmd.encode(&mut output);
+
+        assert_eq!(cmd.size(), expected.len());
+        assert_eq!(
+            output[..expected.len()],
+            expected[..],
+            "{:?}",
+            core::str::from_utf8(expected)
+        );
+    }
+
+    #[test]
+    fn rfc959_commands() {
+        let cases: [(&dyn Command, &[u8]); 30] = [
+            (&Cwd::new("/pub/dir"), b"CWD /pub/dir\r\n"),
+            (&Cdup, b"CDUP\r\n"),
+            (&Pwd, b"PWD\r\n"),
+            (&Mkd::new("new dir"), b"MKD new dir\r\n"),
+            (&Rmd::new("old"), b"RMD old\r\n"),
+            (&Dele::new("file.txt"), b"DELE file.txt\r\n"),
+            (&Rnfr::new("from"), b"RNFR from\r\n"),
+            (&Rnto::new("to"), b"RNTO to\r\n"),
+            (&Noop, b"NOOP\r\n"),
+            (&Quit, b"QUIT\r\n"),
+            (&Abor, b"ABOR\r\n"),
+            (&Rein, b"REIN\r\n"),
+            (&Acct::new("billing"), b"ACCT billing\r\n"),
+            (&Smnt::new("/mnt"), b"SMNT /mnt\r\n"),
+            (&Mode::Stream, b"MODE S\r\n"),
+            (&Mode::Block, b"MODE B\r\n"),
+            (&Mode::Compressed, b"MODE C\r\n"),
+            (&Stru::File, b"STRU F\r\n"),
+            (&Stru::Record, b"STRU R\r\n"),
+            (&Stru::Page, b"STRU P\r\n"),
+            (&Allo::new(1024), b"ALLO 1024\r\n"),
+            (&Allo::with_record_size(1024, 128), b"ALLO 1024 R 128\r\n"),
+            (&Appe::new("log.txt"), b"APPE log.txt\r\n"),
+            (&Stou, b"STOU\r\n"),
+            (&Retr::new("file.txt"), b"RETR file.txt\r\n"),
+            (&Nlst::new("/pub"), b"NLST /pub\r\n"),
+            (
+                &Site::new("CHMOD 644 file.txt"),
+                b"SITE CHMOD 644 file.txt\r\n",
+            ),
+            (&Stat::new(Some("/pub")), b"STAT /pub\r\n"),
+            (&Help::default(), b"HELP\r\n"),
+            (&Help::new(Some("SITE")), b"HELP SITE\r\n"),
+        ];
+
+        for (cmd, expected) in cases {
+            assert_encodes(cmd, expected);
+        }
+
+        assert_encodes(&Stat::default(), b"STAT\r\n");
+        assert_encodes(&Rest::new(0), b"REST 0\r\n");
+    }
+
+    #[test]
+    fn injected_command() {
+        let cases: [&dyn Command; 7] = [
+            &Retr::new("a.txt\r\nDELE b.txt"),
+            &Stor::new("a.txt\nDELE b.txt"),
+            &User::new("user\rPASS"),
+            &Rnfr::new("a\0b"),
+            &Rnto::new("b\r\n"),
+            &Site::new("CHMOD 644 a.txt\r\nSITE EXEC"),
+            &Stat::new(Some("/pub\r\nQUIT")),
+        ];
+
+        for cmd in cases {
+            assert!(matches!(cmd.check(), Err(Error::InvalidArgument)));
+        }
+
+        assert!(Retr::new("a b.txt").check().is_ok());
+        assert!(Stat::default().check().is_ok());
+        assert!(Pasv.check().is_ok());
+        assert!(Rest::new(0).check().is_ok());
+    }
 }
@@ -0,0 +1,57 @@
+/// How paths and reply text are encoded on the control connection.
+///
+/// [RFC 2640](https://www.rfc-editor.org/rfc/rfc2640) recommends UTF-8 but
+/// older servers often use Latin-1.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO 8859-1, characters outside of it are sent as `?`.
+    Latin1,
+}
+
+#[cfg(feature = "std")]
+impl Encoding {
+    pub fn encode(self, text: &str) -> std::borrow::Cow<'_, [u8]> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().into(),
+            Encoding::Latin1 if text.is_ascii() => text.as_bytes().into(),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect::<std::vec::Vec<_>>()
+                .into(),
+        }
+    }
+
+    /// Decodes `bytes`, replacing invalid UTF-8.
+    pub fn decode(self, bytes: &[u8]) -> std::borrow::Cow<'_, str> {
+        match self {
+            Encoding::Utf8 => std::string::String::from_utf8_lossy(bytes),
+            Encoding::Latin1 if bytes.is_ascii() => std::string::String::from_utf8_lossy(bytes),
+            Encoding::Latin1 => bytes
+                .iter()
+                .map(|byte| char::from(*byte))
+                .collect::<std::string::String>()
+                .into(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::Encoding;
+
+    #[test]
+    fn test_latin1() {
+        assert_eq!(&*Encoding::Latin1.encode("café"), b"caf\xe9");
+        assert_eq!(&*Encoding::Latin1.encode("日本"), b"??");
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "café");
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(&*Encoding::Utf8.encode("café"), "café".as_bytes());
+        assert_eq!(Encoding::Utf8.decode(b"caf\xe9"), "caf\u{fffd}");
+    }
+}
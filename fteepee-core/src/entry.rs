@@ -0,0 +1,177 @@
+use core::fmt;
+
+/// The kind of file a directory entry refers to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// Unix permission bits of a directory entry, e.g. `0o755`.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct Permissions(u16);
+
+impl Permissions {
+    pub fn from_mode(mode: u16) -> Self {
+        Self(mode & 0o7777)
+    }
+
+    pub fn mode(&self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Permissions({:#o})", self.0)
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SYMBOLS: [(u16, char); 9] = [
+            (0o400, 'r'),
+            (0o200, 'w'),
+            (0o100, 'x'),
+            (0o040, 'r'),
+            (0o020, 'w'),
+            (0o010, 'x'),
+            (0o004, 'r'),
+            (0o002, 'w'),
+            (0o001, 'x'),
+        ];
+
+        for (bit, symbol) in SYMBOLS {
+            if self.0 & bit == bit {
+                write!(f, "{}", symbol)?;
+            } else {
+                write!(f, "-")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A calendar date and time as reported by the server.
+///
+/// No timezone conversion is performed, MLSD timestamps are in UTC while LIST
+/// timestamps are usually in the server's local time.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch into a UTC date and time.
+    pub fn from_unix_timestamp(secs: u64) -> Self {
+        // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+        let days = (secs / 86_400) as i64 + 719_468;
+        let secs = secs % 86_400;
+
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs % 3600 / 60) as u8,
+            second: (secs % 60) as u8,
+            nanosecond: 0,
+        }
+    }
+
+    /// The current UTC date and time.
+    #[cfg(feature = "std")]
+    pub fn now() -> Self {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self {
+            nanosecond: elapsed.subsec_nanos(),
+            ..Self::from_unix_timestamp(elapsed.as_secs())
+        }
+    }
+}
+
+/// A single entry of a directory listing.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: Option<u64>,
+    pub modified: Option<DateTime>,
+    pub permissions: Option<Permissions>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// Where a symbolic link points to, if the server reported it.
+    pub target: Option<String>,
+    /// The line as it was sent by the server.
+    pub raw: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DateTime, Permissions};
+
+    #[test]
+    fn test_permissions_display() {
+        assert_eq!(Permissions::from_mode(0o755).to_string(), "rwxr-xr-x");
+        assert_eq!(Permissions::from_mode(0o640).to_string(), "rw-r-----");
+        assert_eq!(Permissions::from_mode(0o100644).mode(), 0o644);
+    }
+
+    #[test]
+    fn test_datetime_from_unix_timestamp() {
+        assert_eq!(
+            DateTime::from_unix_timestamp(0),
+            DateTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                ..DateTime::default()
+            }
+        );
+        assert_eq!(
+            DateTime::from_unix_timestamp(1_672_574_400),
+            DateTime {
+                year: 2023,
+                month: 1,
+                day: 1,
+                hour: 12,
+                ..DateTime::default()
+            }
+        );
+        assert_eq!(
+            DateTime::from_unix_timestamp(951_825_599),
+            DateTime {
+                year: 2000,
+                month: 2,
+                day: 29,
+                hour: 11,
+                minute: 59,
+                second: 59,
+                nanosecond: 0,
+            }
+        );
+    }
+}
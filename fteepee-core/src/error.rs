@@ -1,28 +1,81 @@
 use core::fmt;
 
+#[cfg(feature = "std")]
 use crate::Code;
 
 #[derive(Debug)]
 pub enum Error {
     IO(fmt::Error),
+    /// Data was received after the server agreed to a TLS handshake, it could
+    /// have been injected by an attacker.
+    DataBeforeHandshake,
+    /// The server refused to act on `path`.
+    #[cfg(feature = "std")]
+    File {
+        kind: FileError,
+        path: std::string::String,
+        message: std::string::String,
+    },
     IncompleteResponse,
+    /// A command argument contains CR, LF or NUL, which would end the command
+    /// early and let the rest be taken for another.
+    InvalidArgument,
     InvalidCode([u8; 3]),
+    InvalidEntry,
+    InvalidExtendedPassiveMode,
+    /// A FEAT line is not valid UTF-8.
+    InvalidFeature,
     InvalidLineOp,
     InvalidNumber(btoi::ParseIntegerError),
-    UnexpectedCode(Code),
+    /// A multiline reply was ended by a line with a different code.
+    MismatchedReplyCode {
+        expected: [u8; 3],
+        found: [u8; 3],
+    },
+    InvalidPassiveMode,
+    InvalidPathname,
+    /// Connecting through a proxy failed.
+    Proxy(ProxyError),
+    /// A reply went over the [`Limits`](crate::response::Limits) it was
+    /// parsed with.
+    ReplyTooLarge,
+    #[cfg(feature = "std")]
+    UnexpectedCode(crate::Reply),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::IO(err) => err.fmt(f),
+            Error::DataBeforeHandshake => write!(f, "unexpected data before TLS handshake"),
+            #[cfg(feature = "std")]
+            Error::File {
+                kind,
+                path,
+                message,
+            } => write!(f, "{:?} {}: {}", path, kind, message),
             Error::IncompleteResponse => write!(f, "incomplete response"),
+            Error::InvalidArgument => write!(f, "command argument contains CR, LF or NUL"),
             Error::InvalidCode(code) => {
                 write!(f, "invalid reply code {:?}", core::str::from_utf8(code))
             }
+            Error::InvalidEntry => write!(f, "invalid directory entry"),
+            Error::InvalidExtendedPassiveMode => write!(f, "invalid extended passive mode reply"),
+            Error::InvalidFeature => write!(f, "feature is not valid UTF-8"),
             Error::InvalidLineOp => write!(f, "expected either '-' or ' '"),
-            Error::UnexpectedCode(code) => write!(f, "unexpected reply code {:?}", code),
+            Error::ReplyTooLarge => write!(f, "reply too large"),
+            #[cfg(feature = "std")]
+            Error::UnexpectedCode(reply) => write!(f, "unexpected reply {}", reply),
             Error::InvalidNumber(err) => err.fmt(f),
+            Error::MismatchedReplyCode { expected, found } => write!(
+                f,
+                "multiline reply {} ended with code {}",
+                core::str::from_utf8(expected).unwrap_or_default(),
+                core::str::from_utf8(found).unwrap_or_default()
+            ),
+            Error::InvalidPassiveMode => write!(f, "invalid passive mode reply"),
+            Error::InvalidPathname => write!(f, "invalid pathname reply"),
+            Error::Proxy(err) => err.fmt(f),
         }
     }
 }
@@ -30,6 +83,251 @@ impl fmt::Display for Error {
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
+impl Error {
+    /// Categorizes the error, e.g. to decide whether to retry.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::IO(_) | Error::InvalidArgument => ErrorKind::Other,
+            #[cfg(feature = "std")]
+            Error::File { kind, message, .. } => ErrorKind::from_file_error(*kind, message),
+            #[cfg(feature = "std")]
+            Error::UnexpectedCode(reply) => ErrorKind::from_reply(reply),
+            Error::Proxy(err) => err.kind(),
+            Error::DataBeforeHandshake
+            | Error::IncompleteResponse
+            | Error::InvalidCode(_)
+            | Error::InvalidEntry
+            | Error::InvalidExtendedPassiveMode
+            | Error::InvalidFeature
+            | Error::InvalidLineOp
+            | Error::InvalidNumber(_)
+            | Error::MismatchedReplyCode { .. }
+            | Error::InvalidPassiveMode
+            | Error::InvalidPathname
+            | Error::ReplyTooLarge => ErrorKind::ProtocolViolation,
+        }
+    }
+
+    /// Whether the operation could succeed if it is tried again, possibly on
+    /// a new connection.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+/// The category of an [`Error`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server refused the credentials, or requires logging in first.
+    AuthFailed,
+    /// The file or directory does not exist.
+    NotFound,
+    /// The server does not allow the operation.
+    PermissionDenied,
+    /// The server ran out of space or the account went over its quota.
+    StorageFull,
+    /// The server reported a failure that may go away, a 4yz reply.
+    Transient,
+    /// The server sent something that is not valid FTP, or not what the
+    /// command expects.
+    ProtocolViolation,
+    /// The connection was closed or is being closed by the server.
+    ConnectionClosed,
+    /// Any other failure, such as a command the server does not support.
+    Other,
+}
+
+impl ErrorKind {
+    /// Whether an operation that failed this way could succeed if it is
+    /// tried again, possibly on a new connection.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorKind::Transient | ErrorKind::ConnectionClosed)
+    }
+
+    /// Categorizes a failure to act on a file, a 550 reply is used both for
+    /// files that do not exist and for those that cannot be accessed so its
+    /// message is checked to tell them apart.
+    #[cfg(feature = "std")]
+    pub fn from_file_error(kind: FileError, message: &str) -> Self {
+        match kind {
+            FileError::Unavailable => {
+                let message = message.to_ascii_lowercase();
+
+                if ["permission", "denied", "access"]
+                    .iter()
+                    .any(|word| message.contains(word))
+                {
+                    ErrorKind::PermissionDenied
+                } else {
+                    ErrorKind::NotFound
+                }
+            }
+            FileError::NameNotAllowed => ErrorKind::PermissionDenied,
+        }
+    }
+
+    /// Categorizes a reply the command did not expect.
+    #[cfg(feature = "std")]
+    pub fn from_reply(reply: &crate::Reply) -> Self {
+        match reply.code {
+            Code::NOT_LOGGED_IN
+            | Code::NEED_ACCOUNT_FOR_LOGIN
+            | Code::NEED_ACCOUNT_FOR_STORING_FILES => ErrorKind::AuthFailed,
+            Code::FILE_UNAVAILABLE => {
+                Self::from_file_error(FileError::Unavailable, &reply.message())
+            }
+            Code::FILE_NAME_NOT_ALLOWED
+            | Code::COMMAND_PROTECTION_DENIED
+            | Code::REQUEST_DENIED => ErrorKind::PermissionDenied,
+            Code::INSUFFICIENT_STORAGE_SPACE | Code::EXCEEDED_STORAGE_ALLOCATION => {
+                ErrorKind::StorageFull
+            }
+            Code::SERVICE_NOT_AVAILABLE => ErrorKind::ConnectionClosed,
+            code if code.is_transient_failure() => ErrorKind::Transient,
+            code if code.is_permanent_failure() => ErrorKind::Other,
+            _ => ErrorKind::ProtocolViolation,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::AuthFailed => write!(f, "authentication failed"),
+            ErrorKind::NotFound => write!(f, "not found"),
+            ErrorKind::PermissionDenied => write!(f, "permission denied"),
+            ErrorKind::StorageFull => write!(f, "storage full"),
+            ErrorKind::Transient => write!(f, "transient failure"),
+            ErrorKind::ProtocolViolation => write!(f, "protocol violation"),
+            ErrorKind::ConnectionClosed => write!(f, "connection closed"),
+            ErrorKind::Other => write!(f, "other error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::ErrorKind> for ErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        use std::io::ErrorKind as IoErrorKind;
+
+        match kind {
+            IoErrorKind::NotFound => ErrorKind::NotFound,
+            IoErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            IoErrorKind::StorageFull => ErrorKind::StorageFull,
+            IoErrorKind::UnexpectedEof
+            | IoErrorKind::ConnectionReset
+            | IoErrorKind::ConnectionAborted
+            | IoErrorKind::BrokenPipe
+            | IoErrorKind::NotConnected => ErrorKind::ConnectionClosed,
+            IoErrorKind::TimedOut | IoErrorKind::Interrupted | IoErrorKind::WouldBlock => {
+                ErrorKind::Transient
+            }
+            IoErrorKind::InvalidData => ErrorKind::ProtocolViolation,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ErrorKind> for std::io::ErrorKind {
+    fn from(kind: ErrorKind) -> Self {
+        use std::io::ErrorKind as IoErrorKind;
+
+        match kind {
+            ErrorKind::AuthFailed | ErrorKind::PermissionDenied => IoErrorKind::PermissionDenied,
+            ErrorKind::NotFound => IoErrorKind::NotFound,
+            ErrorKind::StorageFull => IoErrorKind::StorageFull,
+            // Interrupted would be retried straight away by std's read and
+            // write loops, TimedOut is what callers already retry on
+            ErrorKind::Transient => IoErrorKind::TimedOut,
+            ErrorKind::ProtocolViolation => IoErrorKind::InvalidData,
+            ErrorKind::ConnectionClosed => IoErrorKind::ConnectionAborted,
+            ErrorKind::Other => IoErrorKind::Other,
+        }
+    }
+}
+
+/// Why a proxy did not connect to the server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProxyError {
+    /// The reply was not valid for the proxy's protocol.
+    InvalidReply,
+    /// The proxy requires credentials, or refused the ones given.
+    AuthFailed,
+    /// A SOCKS5 username or password is over 255 bytes.
+    CredentialsTooLong,
+    /// A host name sent to a SOCKS5 proxy is over 255 bytes.
+    HostTooLong,
+    /// The SOCKS5 proxy failed with this reply code.
+    Socks5(u8),
+    /// The HTTP proxy failed with this status code.
+    Http(u16),
+}
+
+impl ProxyError {
+    pub fn kind(self) -> ErrorKind {
+        match self {
+            ProxyError::InvalidReply => ErrorKind::ProtocolViolation,
+            ProxyError::AuthFailed | ProxyError::CredentialsTooLong => ErrorKind::AuthFailed,
+            // Not allowed by the ruleset
+            ProxyError::Socks5(2) | ProxyError::Http(403) => ErrorKind::PermissionDenied,
+            // Unreachable, refused or timed out
+            ProxyError::Socks5(3..=6) | ProxyError::Http(502..=504) => ErrorKind::Transient,
+            ProxyError::HostTooLong | ProxyError::Socks5(_) | ProxyError::Http(_) => {
+                ErrorKind::Other
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::InvalidReply => write!(f, "invalid proxy reply"),
+            ProxyError::AuthFailed => write!(f, "proxy authentication failed"),
+            ProxyError::CredentialsTooLong => write!(f, "proxy credentials too long"),
+            ProxyError::HostTooLong => write!(f, "host name too long for proxy"),
+            ProxyError::Socks5(code) => {
+                let reason = match code {
+                    1 => "general failure",
+                    2 => "connection not allowed by ruleset",
+                    3 => "network unreachable",
+                    4 => "host unreachable",
+                    5 => "connection refused",
+                    6 => "TTL expired",
+                    7 => "command not supported",
+                    8 => "address type not supported",
+                    _ => "unknown error",
+                };
+
+                write!(f, "SOCKS5 proxy failed to connect: {}", reason)
+            }
+            ProxyError::Http(status) => {
+                write!(f, "HTTP proxy failed to connect with status {}", status)
+            }
+        }
+    }
+}
+
+/// Why the server refused to act on a file or directory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileError {
+    /// 550, e.g. the file does not exist or cannot be accessed.
+    Unavailable,
+    /// 553, the name is not allowed.
+    NameNotAllowed,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Unavailable => write!(f, "is unavailable"),
+            FileError::NameNotAllowed => write!(f, "is not an allowed name"),
+        }
+    }
+}
+
 impl From<fmt::Error> for Error {
     fn from(err: fmt::Error) -> Self {
         Self::IO(err)
@@ -41,3 +339,95 @@ impl From<btoi::ParseIntegerError> for Error {
         Self::InvalidNumber(err)
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{Error, ErrorKind, FileError, ProxyError};
+    use crate::{Code, Reply};
+
+    fn unexpected(code: Code, message: &str) -> Error {
+        Error::UnexpectedCode(Reply::from_message(code, message.as_bytes()))
+    }
+
+    #[test]
+    fn test_kind() {
+        let cases = [
+            (
+                unexpected(Code::NOT_LOGGED_IN, "Login incorrect."),
+                ErrorKind::AuthFailed,
+            ),
+            (
+                unexpected(Code::FILE_UNAVAILABLE, "No such file"),
+                ErrorKind::NotFound,
+            ),
+            (
+                unexpected(Code::FILE_UNAVAILABLE, "Permission denied"),
+                ErrorKind::PermissionDenied,
+            ),
+            (
+                unexpected(Code::EXCEEDED_STORAGE_ALLOCATION, "Quota exceeded"),
+                ErrorKind::StorageFull,
+            ),
+            (
+                unexpected(Code::CANT_OPEN_DATA_CONNECTION, "Try again"),
+                ErrorKind::Transient,
+            ),
+            (
+                unexpected(Code::SERVICE_NOT_AVAILABLE, "Shutting down"),
+                ErrorKind::ConnectionClosed,
+            ),
+            (
+                unexpected(Code::LOGGED_IN, "Welcome"),
+                ErrorKind::ProtocolViolation,
+            ),
+            (unexpected(Code::NOT_IMPLEMENTED, "No"), ErrorKind::Other),
+            (
+                Error::File {
+                    kind: FileError::NameNotAllowed,
+                    path: "a".into(),
+                    message: "Bad name".into(),
+                },
+                ErrorKind::PermissionDenied,
+            ),
+            (Error::ReplyTooLarge, ErrorKind::ProtocolViolation),
+            (Error::InvalidArgument, ErrorKind::Other),
+            (Error::Proxy(ProxyError::HostTooLong), ErrorKind::Other),
+            (Error::Proxy(ProxyError::Socks5(5)), ErrorKind::Transient),
+            (
+                Error::Proxy(ProxyError::Http(403)),
+                ErrorKind::PermissionDenied,
+            ),
+        ];
+
+        for (err, kind) in cases {
+            assert_eq!(err.kind(), kind, "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(unexpected(Code::LOCAL_ERROR_IN_PROCESSING, "Oops").is_retryable());
+        assert!(!unexpected(Code::FILE_UNAVAILABLE, "No such file").is_retryable());
+        assert!(!Error::InvalidPassiveMode.is_retryable());
+    }
+
+    #[test]
+    fn test_io_kind() {
+        assert_eq!(
+            std::io::ErrorKind::from(ErrorKind::Transient),
+            std::io::ErrorKind::TimedOut
+        );
+
+        for kind in [
+            ErrorKind::NotFound,
+            ErrorKind::PermissionDenied,
+            ErrorKind::StorageFull,
+            ErrorKind::Transient,
+            ErrorKind::ProtocolViolation,
+            ErrorKind::ConnectionClosed,
+            ErrorKind::Other,
+        ] {
+            assert_eq!(ErrorKind::from(std::io::ErrorKind::from(kind)), kind);
+        }
+    }
+}
@@ -2,33 +2,134 @@
 
 mod code;
 pub mod commands;
+mod encoding;
+mod entry;
 mod error;
-mod parsers;
+pub mod parsers;
+#[cfg(feature = "std")]
+pub mod proxy;
+#[cfg(feature = "std")]
+mod reply;
 pub mod response;
+#[cfg(feature = "std")]
+pub mod session;
 
 #[cfg(feature = "std")]
 extern crate std as core;
 
 pub use crate::code::Code;
-pub use crate::error::Error;
+pub use crate::encoding::Encoding;
+pub use crate::entry::{DateTime, EntryKind, Permissions};
+pub use crate::error::{Error, ErrorKind, FileError, ProxyError};
 
 #[cfg(feature = "std")]
-pub use crate::parsers::parse_features;
+pub use crate::entry::DirEntry;
+#[cfg(feature = "std")]
+pub use crate::parsers::{parse_features, parse_list_entry, parse_mlsd_entry, parse_pathname};
+#[cfg(feature = "std")]
+pub use crate::proxy::{Proxy, Target, ToTarget};
+#[cfg(feature = "std")]
+pub use crate::reply::Reply;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-#[derive(Default)]
 pub struct Config {
     pub mlst_supported: bool,
+    pub epsv_supported: bool,
+    /// Listing format implied by the server's SYST reply.
+    pub list_format: Option<parsers::ListFormat>,
+    pub data_mode: DataMode,
+    pub reply_limits: response::Limits,
+    /// Type used for uploads and downloads.
+    pub transfer_type: TransferType,
+    pub encoding: Encoding,
+    /// Whether to ask the server what it supports with FEAT once connected,
+    /// otherwise only commands from RFC 959 are used.
+    pub probe_features: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mlst_supported: false,
+            epsv_supported: false,
+            list_format: None,
+            data_mode: DataMode::default(),
+            reply_limits: response::Limits::default(),
+            transfer_type: TransferType::default(),
+            encoding: Encoding::default(),
+            probe_features: true,
+        }
+    }
+}
+
+/// How file contents are represented during a transfer, see
+/// [RFC 959](https://www.rfc-editor.org/rfc/rfc959#section-3.1.1).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TransferType {
+    /// Bytes are transferred as they are, using TYPE I.
+    #[default]
+    Binary,
+    /// Line endings are converted to and from CRLF, using TYPE A.
+    Ascii,
+}
+
+/// How data connections are established.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum DataMode {
+    /// The client connects to the server, using EPSV or PASV.
+    #[default]
+    Passive,
+    /// The server connects to the client, using EPRT or PORT.
+    Active {
+        /// Address sent to the server, for when the client is behind NAT.
+        /// Defaults to the local address of the control connection.
+        external_ip: Option<core::net::IpAddr>,
+        /// Local ports to listen on, defaults to any free port.
+        ports: Option<core::ops::RangeInclusive<u16>>,
+    },
+}
+
+/// How long a client waits before giving up, `None` waits forever.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Timeouts {
+    /// Opening the control connection or a passive data connection.
+    pub connect: Option<core::time::Duration>,
+    /// Each reply, from the command being sent until the reply is complete.
+    pub reply: Option<core::time::Duration>,
+    /// Nothing being sent or received on a data connection, including the
+    /// server not connecting in active mode.
+    pub data_idle: Option<core::time::Duration>,
+}
+
+/// What a client was waiting for when it timed out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeoutPhase {
+    Connect,
+    Reply,
+    Data,
+}
+
+impl core::fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "connecting"),
+            TimeoutPhase::Reply => write!(f, "waiting for a reply"),
+            TimeoutPhase::Data => write!(f, "transferring data"),
+        }
+    }
 }
 
 // TODO: Handle connection closed?
+/// Fails with [`Error::UnexpectedCode`] unless the code of the [`Reply`]
+/// matches the pattern.
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! expect_code {
     ($reply:expr, $pat:pat $(,)?) => {
-        match $reply {
+        match $reply.code {
             $pat => (),
-            _ => Err($crate::Error::UnexpectedCode($reply))?,
+            _ => return Err($crate::Error::UnexpectedCode($reply).into()),
         }
     };
 }
@@ -0,0 +1,225 @@
+use crate::{DateTime, EntryKind, Error, Result};
+
+/// A single entry of a DOS style LIST listing as sent by IIS and many NAS
+/// servers, e.g. `01-15-23  10:30AM       <DIR>          folder`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DosEntry<'a> {
+    pub kind: EntryKind,
+    /// `None` for directories.
+    pub size: Option<u64>,
+    pub modified: DateTime,
+    pub name: &'a str,
+}
+
+/// Parses a single line of a DOS style LIST listing.
+///
+/// Dates are expected as `MM-DD-YY` or `MM-DD-YYYY`, two digit years below 70
+/// are taken to be in the 21st century. Times may use either the 12 or the 24
+/// hour clock.
+pub fn parse_dos_entry(line: &str) -> Result<DosEntry<'_>> {
+    let (date, rest) = split_field(line).ok_or(Error::InvalidEntry)?;
+    let (time, rest) = split_field(rest).ok_or(Error::InvalidEntry)?;
+    let (size, name) = split_field(rest).ok_or(Error::InvalidEntry)?;
+
+    let mut modified = parse_date(date)?;
+    let (hour, minute) = parse_time(time)?;
+
+    modified.hour = hour;
+    modified.minute = minute;
+
+    let (kind, size) = if size.eq_ignore_ascii_case("<DIR>") {
+        (EntryKind::Directory, None)
+    } else {
+        (EntryKind::File, Some(parse_size(size)?))
+    };
+
+    if name.is_empty() {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok(DosEntry {
+        kind,
+        size,
+        modified,
+        name,
+    })
+}
+
+fn split_field(line: &str) -> Option<(&str, &str)> {
+    let (field, rest) = line.trim_start_matches(' ').split_once(' ')?;
+
+    Some((field, rest.trim_start_matches(' ')))
+}
+
+fn parse_date(date: &str) -> Result<DateTime> {
+    let mut parts = date.split(['-', '/']);
+
+    let (month, day, year) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(month), Some(day), Some(year), None) => (month, day, year),
+        _ => return Err(Error::InvalidEntry),
+    };
+
+    let month = btoi::btou(month.as_bytes())?;
+    let day = btoi::btou(day.as_bytes())?;
+
+    let year = match year.len() {
+        2 => match btoi::btou::<u16>(year.as_bytes())? {
+            year @ 0..=69 => 2000 + year,
+            year => 1900 + year,
+        },
+        4 => btoi::btou(year.as_bytes())?,
+        _ => return Err(Error::InvalidEntry),
+    };
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok(DateTime {
+        year,
+        month,
+        day,
+        ..DateTime::default()
+    })
+}
+
+fn parse_time(time: &str) -> Result<(u8, u8)> {
+    let bytes = time.as_bytes();
+
+    let (time, pm) = match bytes.len().checked_sub(2).map(|idx| bytes.split_at(idx)) {
+        Some((time, suffix)) if suffix.eq_ignore_ascii_case(b"AM") => (time, Some(false)),
+        Some((time, suffix)) if suffix.eq_ignore_ascii_case(b"PM") => (time, Some(true)),
+        _ => (bytes, None),
+    };
+
+    let colon = time
+        .iter()
+        .position(|byte| *byte == b':')
+        .ok_or(Error::InvalidEntry)?;
+
+    let hour: u8 = btoi::btou(&time[..colon])?;
+    let minute: u8 = btoi::btou(&time[colon + 1..])?;
+
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return Err(Error::InvalidEntry),
+        Some(false) => hour % 12,
+        Some(true) => hour % 12 + 12,
+        None => hour,
+    };
+
+    if hour > 23 || minute > 59 {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok((hour, minute))
+}
+
+/// Sizes may be grouped with commas by some servers, e.g. `1,234,567`.
+fn parse_size(size: &str) -> Result<u64> {
+    let mut total: u64 = 0;
+    let mut digits = 0;
+
+    for byte in size.bytes() {
+        match byte {
+            b'0'..=b'9' => {
+                total = total
+                    .checked_mul(10)
+                    .and_then(|total| total.checked_add(u64::from(byte - b'0')))
+                    .ok_or(Error::InvalidEntry)?;
+                digits += 1;
+            }
+            b',' if digits > 0 => {}
+            _ => return Err(Error::InvalidEntry),
+        }
+    }
+
+    if digits == 0 {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            ..DateTime::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        assert_eq!(
+            parse_dos_entry("01-15-23  10:30AM       <DIR>          folder").unwrap(),
+            DosEntry {
+                kind: EntryKind::Directory,
+                size: None,
+                modified: date(2023, 1, 15, 10, 30),
+                name: "folder",
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_file() {
+        assert_eq!(
+            parse_dos_entry("12-31-99  11:59PM              1234 my report.docx").unwrap(),
+            DosEntry {
+                kind: EntryKind::File,
+                size: Some(1234),
+                modified: date(1999, 12, 31, 23, 59),
+                name: "my report.docx",
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_variants() {
+        let entry = parse_dos_entry("01/02/2024 00:05 1,234,567 data.bin").unwrap();
+
+        assert_eq!(entry.modified, date(2024, 1, 2, 0, 5));
+        assert_eq!(entry.size, Some(1_234_567));
+
+        let entry = parse_dos_entry("06-15-23  12:00AM  <dir>  midnight").unwrap();
+
+        assert_eq!(entry.kind, EntryKind::Directory);
+        assert_eq!(entry.modified, date(2023, 6, 15, 0, 0));
+
+        let entry = parse_dos_entry("06-15-23  12:00pm  0  noon").unwrap();
+
+        assert_eq!(entry.modified, date(2023, 6, 15, 12, 0));
+        assert_eq!(entry.size, Some(0));
+    }
+
+    #[test]
+    fn test_parse_invalid_lines() {
+        for line in [
+            "",
+            "01-15-23",
+            "01-15-23  10:30AM  <DIR>",
+            "01-15-23  10:30AM  <DIR> ",
+            "13-15-23  10:30AM  <DIR>  x",
+            "01-32-23  10:30AM  <DIR>  x",
+            "01-15-123  10:30AM  <DIR>  x",
+            "01-15  10:30AM  <DIR>  x",
+            "01-15-23  13:30PM  <DIR>  x",
+            "01-15-23  00:30AM  <DIR>  x",
+            "01-15-23  24:00  <DIR>  x",
+            "01-15-23  1030AM  <DIR>  x",
+            "01-15-23  10:30AM  ,123  x",
+            "01-15-23  10:30AM  12a  x",
+            "01-15-23  10:30:00AM  12  x",
+            "01-15-23  10:30AM  99999999999999999999  x",
+        ] {
+            assert!(parse_dos_entry(line).is_err(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+#[cfg(feature = "std")]
+use crate::{DateTime, DirEntry, Permissions, Result};
+
+#[cfg(feature = "std")]
+use super::{parse_dos_entry, parse_unix_entry};
+
+/// The format of the lines sent in reply to LIST.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ListFormat {
+    /// `drwxr-xr-x 2 user group 4096 Jan  1 12:00 name`
+    Unix,
+    /// `01-15-23  10:30AM       <DIR>          name`
+    Dos,
+}
+
+impl ListFormat {
+    /// Guesses the format of a single listing line.
+    ///
+    /// Unix lines start with the file type and permissions while DOS lines
+    /// start with the date, so the first character is a reliable indicator.
+    pub fn detect(line: &str) -> Option<Self> {
+        let bytes = line.as_bytes();
+
+        match bytes {
+            [b'-' | b'b' | b'c' | b'd' | b'l' | b'p' | b's' | b'D', rest @ ..]
+                if rest.len() >= 9 && rest[..9].iter().all(|b| b"rwxsStT-".contains(b)) =>
+            {
+                Some(Self::Unix)
+            }
+            [b'0'..=b'9', b'0'..=b'9', b'-' | b'/', ..] => Some(Self::Dos),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the reply to SYST, e.g. `UNIX Type: L8` or
+    /// `Windows_NT`.
+    pub fn from_system(system: &str) -> Option<Self> {
+        let name = system.split(' ').next().unwrap_or_default();
+
+        if name.eq_ignore_ascii_case("UNIX") {
+            Some(Self::Unix)
+        } else if name.eq_ignore_ascii_case("Windows_NT") || name.eq_ignore_ascii_case("MSDOS") {
+            Some(Self::Dos)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a single line of a LIST listing into a [`DirEntry`], detecting
+/// whether it is in Unix or DOS format.
+///
+/// `hint` is used for lines whose format cannot be detected, typically taken
+/// from [`ListFormat::from_system`]. Unix listings are dated relative to
+/// `now`, see [`parse_unix_entry`].
+///
+/// Returns `None` for lines that do not describe an entry, such as the
+/// `total` line that precedes Unix listings.
+#[cfg(feature = "std")]
+pub fn parse_list_entry(
+    line: &str,
+    hint: Option<ListFormat>,
+    now: DateTime,
+) -> Result<Option<DirEntry>> {
+    if is_total_line(line) {
+        return Ok(None);
+    }
+
+    let entry = match ListFormat::detect(line).or(hint) {
+        Some(ListFormat::Dos) => {
+            let entry = parse_dos_entry(line)?;
+
+            DirEntry {
+                name: entry.name.to_owned(),
+                kind: entry.kind,
+                size: entry.size,
+                modified: Some(entry.modified),
+                permissions: None,
+                owner: None,
+                group: None,
+                target: None,
+                raw: line.to_owned(),
+            }
+        }
+        Some(ListFormat::Unix) | None => {
+            let entry = parse_unix_entry(line, now)?;
+
+            DirEntry {
+                name: entry.name.to_owned(),
+                kind: entry.kind,
+                size: entry.size,
+                modified: Some(entry.modified),
+                permissions: Some(Permissions::from_mode(entry.mode)),
+                owner: entry.owner.map(str::to_owned),
+                group: entry.group.map(str::to_owned),
+                target: entry.target.map(str::to_owned),
+                raw: line.to_owned(),
+            }
+        }
+    };
+
+    Ok(Some(entry))
+}
+
+#[cfg(feature = "std")]
+fn is_total_line(line: &str) -> bool {
+    matches!(
+        line.split_once(' '),
+        Some(("total", count)) if btoi::btou::<u64>(count.trim().as_bytes()).is_ok()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    use crate::EntryKind;
+
+    #[test]
+    fn test_detect() {
+        let cases = [
+            ("-rw-r--r-- 1 u g 0 Jan 1 2020 x", Some(ListFormat::Unix)),
+            ("drwxr-xr-x+ 1 u g 0 Jan 1 2020 x", Some(ListFormat::Unix)),
+            ("lrwxrwxrwx 1 u g 0 Jan 1 2020 x", Some(ListFormat::Unix)),
+            ("crw-rw-rw- 1 u g 1, 3 Jan 1 2020 x", Some(ListFormat::Unix)),
+            ("drwxrwxrwt 1 u g 0 Jan 1 2020 x", Some(ListFormat::Unix)),
+            ("01-15-23  10:30AM  <DIR>  x", Some(ListFormat::Dos)),
+            ("01/15/2023  10:30  12  x", Some(ListFormat::Dos)),
+            ("total 12", None),
+            ("dir name", None),
+            ("", None),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(ListFormat::detect(line), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_from_system() {
+        assert_eq!(
+            ListFormat::from_system("UNIX Type: L8"),
+            Some(ListFormat::Unix)
+        );
+        assert_eq!(ListFormat::from_system("Windows_NT"), Some(ListFormat::Dos));
+        assert_eq!(
+            ListFormat::from_system("Windows_NT version 10.0"),
+            Some(ListFormat::Dos)
+        );
+        assert_eq!(ListFormat::from_system("MACOS Peter's Server"), None);
+        assert_eq!(ListFormat::from_system(""), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_mixed_listing() {
+        let now = DateTime {
+            year: 2023,
+            month: 6,
+            day: 15,
+            ..DateTime::default()
+        };
+
+        let lines = [
+            "total 8",
+            "drwxr-xr-x 2 user group 4096 Jun 1 12:00 unix dir",
+            "lrwxrwxrwx 1 root root 4 Jun 1 2020 lib -> usr/lib",
+            "01-15-23  10:30AM       <DIR>          dos dir",
+            "01-15-23  10:31AM                42 dos file.txt",
+        ];
+
+        let entries = lines
+            .iter()
+            .filter_map(|line| parse_list_entry(line, None, now).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 4);
+
+        assert_eq!(entries[0].name, "unix dir");
+        assert_eq!(entries[0].kind, EntryKind::Directory);
+        assert_eq!(entries[0].owner.as_deref(), Some("user"));
+
+        assert_eq!(entries[1].name, "lib");
+        assert_eq!(entries[1].kind, EntryKind::Symlink);
+        assert_eq!(entries[1].target.as_deref(), Some("usr/lib"));
+
+        assert_eq!(entries[2].name, "dos dir");
+        assert_eq!(entries[2].kind, EntryKind::Directory);
+        assert_eq!(entries[2].permissions, None);
+
+        assert_eq!(entries[3].name, "dos file.txt");
+        assert_eq!(entries[3].kind, EntryKind::File);
+        assert_eq!(entries[3].size, Some(42));
+        assert_eq!(entries[3].raw, lines[4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_invalid_line() {
+        assert!(parse_list_entry("garbage", None, DateTime::default()).is_err());
+        assert!(parse_list_entry("garbage", Some(ListFormat::Dos), DateTime::default()).is_err());
+    }
+}
@@ -0,0 +1,620 @@
+use crate::{DateTime, Error, Result};
+
+#[cfg(feature = "std")]
+use crate::{DirEntry, EntryKind, Permissions};
+
+/// A single entry of an MLSD listing or MLST reply, as described in
+/// [RFC 3659](https://www.rfc-editor.org/rfc/rfc3659#section-7).
+///
+/// Facts are parsed lazily, see [`MlstEntry::facts`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MlstEntry<'a> {
+    facts: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> MlstEntry<'a> {
+    pub fn facts(&self) -> Facts<'a> {
+        Facts { rest: self.facts }
+    }
+}
+
+/// Iterator over the facts of an [`MlstEntry`].
+#[derive(Clone, Debug)]
+pub struct Facts<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Facts<'a> {
+    type Item = Result<Fact<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let (fact, rest) = match self.rest.split_once(';') {
+            Some(split) => split,
+            None => (self.rest, ""),
+        };
+
+        self.rest = rest;
+
+        Some(parse_fact(fact))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fact<'a> {
+    Type(FactType<'a>),
+    Size(u64),
+    /// Size of the directory listing itself.
+    Sizd(u64),
+    Modify(DateTime),
+    Create(DateTime),
+    Perm(Perm),
+    Unique(&'a str),
+    Lang(&'a str),
+    MediaType(&'a str),
+    Charset(&'a str),
+    UnixMode(u32),
+    UnixUid(u32),
+    UnixGid(u32),
+    /// A fact not covered above, `name` keeps its original case.
+    Unknown {
+        name: &'a str,
+        value: &'a str,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FactType<'a> {
+    File,
+    Dir,
+    /// The listed directory itself.
+    CurrentDir,
+    /// The parent of the listed directory.
+    ParentDir,
+    /// `OS.unix=symlink` or `OS.unix=slink`, optionally followed by `:target`.
+    Symlink(Option<&'a str>),
+    /// Any other operating system specific type, e.g. `OS.unix=chr-13/29`.
+    Os(&'a str),
+    Unknown(&'a str),
+}
+
+/// The `perm` fact, describing which operations the user may perform on an
+/// entry.
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+pub struct Perm(u16);
+
+impl Perm {
+    /// `a` - APPE may be used on the file.
+    pub const APPEND: Perm = Perm(1 << 0);
+    /// `c` - STOR may be used to create files in the directory.
+    pub const CREATE: Perm = Perm(1 << 1);
+    /// `d` - The object may be deleted.
+    pub const DELETE: Perm = Perm(1 << 2);
+    /// `e` - CWD may be used to enter the directory.
+    pub const ENTER: Perm = Perm(1 << 3);
+    /// `f` - The object may be renamed.
+    pub const RENAME: Perm = Perm(1 << 4);
+    /// `l` - The directory may be listed.
+    pub const LIST: Perm = Perm(1 << 5);
+    /// `m` - MKD may be used to create directories in the directory.
+    pub const MKDIR: Perm = Perm(1 << 6);
+    /// `p` - Objects in the directory may be deleted.
+    pub const PURGE: Perm = Perm(1 << 7);
+    /// `r` - RETR may be used on the file.
+    pub const READ: Perm = Perm(1 << 8);
+    /// `w` - STOR may be used on the file.
+    pub const WRITE: Perm = Perm(1 << 9);
+
+    const FLAGS: [(u8, Perm); 10] = [
+        (b'a', Perm::APPEND),
+        (b'c', Perm::CREATE),
+        (b'd', Perm::DELETE),
+        (b'e', Perm::ENTER),
+        (b'f', Perm::RENAME),
+        (b'l', Perm::LIST),
+        (b'm', Perm::MKDIR),
+        (b'p', Perm::PURGE),
+        (b'r', Perm::READ),
+        (b'w', Perm::WRITE),
+    ];
+
+    pub fn contains(&self, other: Perm) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn parse(value: &str) -> Self {
+        // Unknown flags are ignored so new ones do not break parsing.
+        value.bytes().fold(Perm::default(), |perm, flag| {
+            match Perm::FLAGS
+                .iter()
+                .find(|(symbol, _)| symbol.eq_ignore_ascii_case(&flag))
+            {
+                Some((_, known)) => Perm(perm.0 | known.0),
+                None => perm,
+            }
+        })
+    }
+}
+
+impl core::fmt::Debug for Perm {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Perm(\"")?;
+        for (symbol, flag) in Perm::FLAGS {
+            if self.contains(flag) {
+                write!(f, "{}", symbol as char)?;
+            }
+        }
+        write!(f, "\")")
+    }
+}
+
+/// Parses an MLSD line or the entry line of an MLST reply, e.g.
+/// `type=file;size=123;modify=20230101120000; name`.
+///
+/// The leading space that prefixes the entry inside an MLST reply must be
+/// removed by the caller.
+pub fn parse_mlst_entry(line: &str) -> Result<MlstEntry<'_>> {
+    let (facts, name) = line.split_once(' ').ok_or(Error::InvalidEntry)?;
+
+    if name.is_empty() {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok(MlstEntry { facts, name })
+}
+
+fn parse_fact(fact: &str) -> Result<Fact<'_>> {
+    let (name, value) = fact.split_once('=').ok_or(Error::InvalidEntry)?;
+
+    let fact = if name.eq_ignore_ascii_case("type") {
+        Fact::Type(parse_type(value))
+    } else if name.eq_ignore_ascii_case("size") {
+        Fact::Size(btoi::btou(value.as_bytes())?)
+    } else if name.eq_ignore_ascii_case("sizd") {
+        Fact::Sizd(btoi::btou(value.as_bytes())?)
+    } else if name.eq_ignore_ascii_case("modify") {
+        Fact::Modify(parse_timestamp(value)?)
+    } else if name.eq_ignore_ascii_case("create") {
+        Fact::Create(parse_timestamp(value)?)
+    } else if name.eq_ignore_ascii_case("perm") {
+        Fact::Perm(Perm::parse(value))
+    } else if name.eq_ignore_ascii_case("unique") {
+        Fact::Unique(value)
+    } else if name.eq_ignore_ascii_case("lang") {
+        Fact::Lang(value)
+    } else if name.eq_ignore_ascii_case("media-type") {
+        Fact::MediaType(value)
+    } else if name.eq_ignore_ascii_case("charset") {
+        Fact::Charset(value)
+    } else if name.eq_ignore_ascii_case("unix.mode") {
+        Fact::UnixMode(btoi::btou_radix(value.as_bytes(), 8)?)
+    } else if name.eq_ignore_ascii_case("unix.uid") {
+        Fact::UnixUid(btoi::btou(value.as_bytes())?)
+    } else if name.eq_ignore_ascii_case("unix.gid") {
+        Fact::UnixGid(btoi::btou(value.as_bytes())?)
+    } else {
+        Fact::Unknown { name, value }
+    };
+
+    Ok(fact)
+}
+
+fn parse_type(value: &str) -> FactType<'_> {
+    if value.eq_ignore_ascii_case("file") {
+        FactType::File
+    } else if value.eq_ignore_ascii_case("dir") {
+        FactType::Dir
+    } else if value.eq_ignore_ascii_case("cdir") {
+        FactType::CurrentDir
+    } else if value.eq_ignore_ascii_case("pdir") {
+        FactType::ParentDir
+    } else if let Some(target) = strip_prefix_ignore_case(value, "os.unix=symlink")
+        .or_else(|| strip_prefix_ignore_case(value, "os.unix=slink"))
+        .filter(|target| target.is_empty() || target.starts_with(':'))
+    {
+        FactType::Symlink(target.strip_prefix(':'))
+    } else if strip_prefix_ignore_case(value, "os.").is_some() {
+        FactType::Os(value)
+    } else {
+        FactType::Unknown(value)
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    match value.get(..prefix.len()) {
+        Some(start) if start.eq_ignore_ascii_case(prefix) => Some(&value[prefix.len()..]),
+        _ => None,
+    }
+}
+
+/// Parses a `YYYYMMDDHHMMSS[.sss]` timestamp.
+fn parse_timestamp(value: &str) -> Result<DateTime> {
+    let (time, fraction) = match value.split_once('.') {
+        Some((time, fraction)) => (time.as_bytes(), Some(fraction.as_bytes())),
+        None => (value.as_bytes(), None),
+    };
+
+    if time.len() != 14 {
+        return Err(Error::InvalidEntry);
+    }
+
+    let nanosecond = match fraction {
+        Some(digits) if !digits.is_empty() && digits.len() <= 9 => {
+            let nanos: u32 = btoi::btou(digits)?;
+            nanos * 10u32.pow(9 - digits.len() as u32)
+        }
+        Some(_) => return Err(Error::InvalidEntry),
+        None => 0,
+    };
+
+    let datetime = DateTime {
+        year: btoi::btou(&time[0..4])?,
+        month: btoi::btou(&time[4..6])?,
+        day: btoi::btou(&time[6..8])?,
+        hour: btoi::btou(&time[8..10])?,
+        minute: btoi::btou(&time[10..12])?,
+        second: btoi::btou(&time[12..14])?,
+        nanosecond,
+    };
+
+    if !(1..=12).contains(&datetime.month)
+        || !(1..=31).contains(&datetime.day)
+        || datetime.hour > 23
+        || datetime.minute > 59
+        // Allow for leap seconds
+        || datetime.second > 60
+    {
+        return Err(Error::InvalidEntry);
+    }
+
+    Ok(datetime)
+}
+
+/// Parses a single line of an MLSD listing into a [`DirEntry`].
+///
+/// Returns `None` for the `cdir` and `pdir` entries describing the listed
+/// directory and its parent.
+#[cfg(feature = "std")]
+pub fn parse_mlsd_entry(line: &str) -> Result<Option<DirEntry>> {
+    let mlst = parse_mlst_entry(line)?;
+
+    let mut entry = DirEntry {
+        name: mlst.name.to_owned(),
+        kind: EntryKind::Other,
+        size: None,
+        modified: None,
+        permissions: None,
+        owner: None,
+        group: None,
+        target: None,
+        raw: line.to_owned(),
+    };
+
+    for fact in mlst.facts() {
+        match fact? {
+            Fact::Type(FactType::CurrentDir | FactType::ParentDir) => return Ok(None),
+            Fact::Type(FactType::File) => entry.kind = EntryKind::File,
+            Fact::Type(FactType::Dir) => entry.kind = EntryKind::Directory,
+            Fact::Type(FactType::Symlink(target)) => {
+                entry.kind = EntryKind::Symlink;
+                entry.target = target.map(str::to_owned);
+            }
+            Fact::Size(size) => entry.size = Some(size),
+            Fact::Modify(modified) => entry.modified = Some(modified),
+            Fact::UnixMode(mode) => entry.permissions = Some(Permissions::from_mode(mode as u16)),
+            Fact::UnixUid(uid) if entry.owner.is_none() => entry.owner = Some(uid.to_string()),
+            Fact::UnixGid(gid) if entry.group.is_none() => entry.group = Some(gid.to_string()),
+            Fact::Unknown { name, value } if name.eq_ignore_ascii_case("unix.owner") => {
+                entry.owner = Some(value.to_owned())
+            }
+            Fact::Unknown { name, value } if name.eq_ignore_ascii_case("unix.group") => {
+                entry.group = Some(value.to_owned())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Some(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts(line: &str) -> Vec<Fact<'_>> {
+        parse_mlst_entry(line)
+            .unwrap()
+            .facts()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_mlst_entry_name() {
+        let entry = parse_mlst_entry("type=file;size=1; name with  spaces ").unwrap();
+        assert_eq!(entry.name, "name with  spaces ");
+
+        let entry = parse_mlst_entry(" no-facts").unwrap();
+        assert_eq!(entry.name, "no-facts");
+        assert_eq!(entry.facts().count(), 0);
+
+        let entry = parse_mlst_entry("type=file; /home/user/semi;colon=file").unwrap();
+        assert_eq!(entry.name, "/home/user/semi;colon=file");
+        assert_eq!(facts("type=file; /home/user/semi;colon=file").len(), 1);
+
+        assert!(parse_mlst_entry("type=file;size=1;").is_err());
+        assert!(parse_mlst_entry("type=file;size=1; ").is_err());
+        assert!(parse_mlst_entry("").is_err());
+    }
+
+    #[test]
+    fn test_parse_types() {
+        let cases = [
+            ("type=file; x", FactType::File),
+            ("type=dir; x", FactType::Dir),
+            ("type=cdir; x", FactType::CurrentDir),
+            ("type=pdir; x", FactType::ParentDir),
+            ("Type=DIR; x", FactType::Dir),
+            ("type=OS.unix=symlink; x", FactType::Symlink(None)),
+            (
+                "type=OS.unix=slink:/target; x",
+                FactType::Symlink(Some("/target")),
+            ),
+            ("type=os.unix=slink; x", FactType::Symlink(None)),
+            ("type=OS.unix=slinky; x", FactType::Os("OS.unix=slinky")),
+            (
+                "type=OS.unix=chr-13/29; x",
+                FactType::Os("OS.unix=chr-13/29"),
+            ),
+            ("type=weird; x", FactType::Unknown("weird")),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(facts(line), [Fact::Type(expected)], "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(
+            facts("size=0;sizd=4096;Size=18446744073709551615; x"),
+            [Fact::Size(0), Fact::Sizd(4096), Fact::Size(u64::MAX)]
+        );
+
+        assert!(parse_mlst_entry("size=-1; x")
+            .unwrap()
+            .facts()
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(parse_mlst_entry("size=; x")
+            .unwrap()
+            .facts()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamps() {
+        let expected = DateTime {
+            year: 2023,
+            month: 1,
+            day: 2,
+            hour: 3,
+            minute: 4,
+            second: 5,
+            nanosecond: 0,
+        };
+
+        assert_eq!(
+            facts("modify=20230102030405;create=20230102030405; x"),
+            [Fact::Modify(expected), Fact::Create(expected)]
+        );
+
+        assert_eq!(
+            facts("modify=20230102030405.5; x"),
+            [Fact::Modify(DateTime {
+                nanosecond: 500_000_000,
+                ..expected
+            })]
+        );
+        assert_eq!(
+            facts("modify=20230102030405.123; x"),
+            [Fact::Modify(DateTime {
+                nanosecond: 123_000_000,
+                ..expected
+            })]
+        );
+        assert_eq!(
+            facts("modify=20230102030405.123456789; x"),
+            [Fact::Modify(DateTime {
+                nanosecond: 123_456_789,
+                ..expected
+            })]
+        );
+
+        for invalid in [
+            "modify=2023010203040; x",
+            "modify=202301020304055; x",
+            "modify=20231302030405; x",
+            "modify=20230100030405; x",
+            "modify=20230102240405; x",
+            "modify=20230102036005; x",
+            "modify=20230102030405.; x",
+            "modify=20230102030405.1234567890; x",
+            "modify=2023010203040a; x",
+        ] {
+            assert!(
+                parse_mlst_entry(invalid)
+                    .unwrap()
+                    .facts()
+                    .next()
+                    .unwrap()
+                    .is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_perm() {
+        let [Fact::Perm(perm)] = facts("perm=adfrw; x")[..] else {
+            panic!("expected a single perm fact");
+        };
+
+        assert!(perm.contains(Perm::APPEND));
+        assert!(perm.contains(Perm::DELETE));
+        assert!(perm.contains(Perm::RENAME));
+        assert!(perm.contains(Perm::READ));
+        assert!(perm.contains(Perm::WRITE));
+        assert!(!perm.contains(Perm::ENTER));
+        assert!(!perm.contains(Perm::LIST));
+        assert_eq!(format!("{:?}", perm), "Perm(\"adfrw\")");
+
+        let [Fact::Perm(perm)] = facts("perm=flcdmpe; x")[..] else {
+            panic!("expected a single perm fact");
+        };
+
+        assert!(perm.contains(Perm::ENTER));
+        assert!(perm.contains(Perm::LIST));
+        assert!(perm.contains(Perm::CREATE));
+        assert!(perm.contains(Perm::MKDIR));
+        assert!(perm.contains(Perm::PURGE));
+        assert!(!perm.contains(Perm::READ));
+
+        let [Fact::Perm(perm)] = facts("perm=xyz; x")[..] else {
+            panic!("expected a single perm fact");
+        };
+
+        assert!(perm.is_empty());
+    }
+
+    #[test]
+    fn test_parse_unix_facts() {
+        assert_eq!(
+            facts("UNIX.mode=0755;UNIX.uid=1000;unix.gid=100;UNIX.owner=user; x"),
+            [
+                Fact::UnixMode(0o755),
+                Fact::UnixUid(1000),
+                Fact::UnixGid(100),
+                Fact::Unknown {
+                    name: "UNIX.owner",
+                    value: "user"
+                },
+            ]
+        );
+
+        assert!(parse_mlst_entry("UNIX.mode=0855; x")
+            .unwrap()
+            .facts()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_string_facts() {
+        assert_eq!(
+            facts("unique=803g6a1f;lang=en;media-type=text/plain;charset=UTF-8;x.custom=; x"),
+            [
+                Fact::Unique("803g6a1f"),
+                Fact::Lang("en"),
+                Fact::MediaType("text/plain"),
+                Fact::Charset("UTF-8"),
+                Fact::Unknown {
+                    name: "x.custom",
+                    value: ""
+                },
+            ]
+        );
+
+        assert!(parse_mlst_entry("novalue; x")
+            .unwrap()
+            .facts()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_full_line() {
+        assert_eq!(
+            facts("type=file;size=1024;modify=20230101120000.25;perm=r;unique=fd00g2;UNIX.mode=0644; report.pdf"),
+            [
+                Fact::Type(FactType::File),
+                Fact::Size(1024),
+                Fact::Modify(DateTime {
+                    year: 2023,
+                    month: 1,
+                    day: 1,
+                    hour: 12,
+                    minute: 0,
+                    second: 0,
+                    nanosecond: 250_000_000,
+                }),
+                Fact::Perm(Perm::READ),
+                Fact::Unique("fd00g2"),
+                Fact::UnixMode(0o644),
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_mlsd_entry() {
+        let entry =
+            parse_mlsd_entry("type=file;size=123;modify=20230101120000;UNIX.mode=0644; a b.txt")
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(entry.name, "a b.txt");
+        assert_eq!(entry.kind, EntryKind::File);
+        assert_eq!(entry.size, Some(123));
+        assert_eq!(
+            entry.modified,
+            Some(DateTime {
+                year: 2023,
+                month: 1,
+                day: 1,
+                hour: 12,
+                ..DateTime::default()
+            })
+        );
+        assert_eq!(entry.permissions, Some(Permissions::from_mode(0o644)));
+
+        let entry = parse_mlsd_entry("Type=dir;Modify=20230101120000; docs")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(entry.name, "docs");
+        assert_eq!(entry.kind, EntryKind::Directory);
+        assert_eq!(entry.size, None);
+
+        let entry = parse_mlsd_entry(
+            "type=OS.unix=slink:/etc/hosts;UNIX.uid=0;UNIX.owner=root;UNIX.gid=0; link",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(entry.kind, EntryKind::Symlink);
+        assert_eq!(entry.target.as_deref(), Some("/etc/hosts"));
+        assert_eq!(entry.owner.as_deref(), Some("root"));
+        assert_eq!(entry.group.as_deref(), Some("0"));
+
+        assert_eq!(parse_mlsd_entry("type=cdir; .").unwrap(), None);
+        assert_eq!(parse_mlsd_entry("type=pdir; ..").unwrap(), None);
+
+        assert!(parse_mlsd_entry("type=file;size=abc; name").is_err());
+        assert!(parse_mlsd_entry("type=file;").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+mod dos;
+mod list;
+mod mlst;
+mod unix;
+
+pub use self::dos::*;
+pub use self::list::*;
+pub use self::mlst::*;
+pub use self::unix::*;
+
+use crate::response::Addr;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take, take_till, take_until, take_while_m_n},
+    character::{complete::digit1, is_digit},
+    combinator::map_res,
+    sequence::{preceded, tuple},
+    Finish, IResult,
+};
+
+use crate::{Error, Result};
+
+/// Parses the features listed in reply to FEAT, keyed by name with any
+/// details that follow it, e.g. `REST STREAM`, see
+/// [RFC 2389](https://www.rfc-editor.org/rfc/rfc2389#section-3.2).
+#[cfg(feature = "std")]
+pub fn parse_features(buf: &[u8]) -> Result<std::collections::HashMap<&str, Option<&str>>> {
+    let lines = buf.split(|byte| *byte == b'\n');
+
+    lines
+        .filter_map(|line| match line {
+            [b' ', rest @ ..] => Some(rest),
+            _ => None,
+        })
+        .map(|line| {
+            let line = core::str::from_utf8(line).map_err(|_| Error::InvalidFeature)?;
+
+            Ok(match line.split_once(' ') {
+                Some((feature, details)) => (feature.trim(), Some(details.trim())),
+                None => (line.trim(), None),
+            })
+        })
+        .collect()
+}
+
+/// Parses the directory out of a 257 reply to PWD or MKD, e.g.
+/// `"/usr/dm" is current directory.`, see
+/// [RFC 959](https://www.rfc-editor.org/rfc/rfc959#page-63).
+///
+/// Quotes that are part of the name are doubled by the server, e.g.
+/// `"say ""yes"""` is the directory `say "yes"`.
+#[cfg(feature = "std")]
+pub fn parse_pathname(buf: &[u8]) -> Result<String> {
+    let start = buf
+        .iter()
+        .position(|byte| *byte == b'"')
+        .ok_or(Error::InvalidPathname)?;
+
+    let mut bytes = buf[start + 1..].iter();
+    let mut pathname = Vec::new();
+
+    loop {
+        match bytes.next() {
+            Some(b'"') if bytes.as_slice().first() == Some(&b'"') => {
+                bytes.next();
+                pathname.push(b'"');
+            }
+            Some(b'"') => break,
+            Some(byte) => pathname.push(*byte),
+            None => return Err(Error::InvalidPathname),
+        }
+    }
+
+    String::from_utf8(pathname).map_err(|_| Error::InvalidPathname)
+}
+
+/// Parses the address out of a PASV reply, e.g.
+/// `Entering Passive Mode (127,0,0,1,117,49)`.
+///
+/// Not every server wraps the address in brackets, as suggested by
+/// [RFC 1123](https://www.rfc-editor.org/rfc/rfc1123#page-31) the reply is
+/// then scanned for the first digit instead.
+pub fn parse_passive_mode(buf: &[u8]) -> Result<Addr> {
+    let (_, addr) = passive_mode(buf)
+        .finish()
+        .map_err(|_| Error::InvalidPassiveMode)?;
+
+    Ok(addr)
+}
+
+/// Parses the port out of an EPSV reply, e.g.
+/// `Entering Extended Passive Mode (|||6446|)`.
+///
+/// The host is not part of the reply, the data connection is made to the
+/// same host as the control connection.
+pub fn parse_extended_passive_mode(buf: &[u8]) -> Result<u16> {
+    let (_, port) = extended_passive_mode(buf)
+        .finish()
+        .map_err(|_| Error::InvalidExtendedPassiveMode)?;
+
+    Ok(port)
+}
+
+/// Parses the size out of a SIZE reply, e.g. `1234`, see
+/// [RFC 3659](https://www.rfc-editor.org/rfc/rfc3659#section-4).
+pub fn parse_size(buf: &[u8]) -> Result<u64> {
+    Ok(btoi::btou(buf.trim_ascii())?)
+}
+
+fn extended_passive_mode(input: &[u8]) -> IResult<&[u8], u16> {
+    let (input, _) = take_until("(")(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, delimiter) = take(1usize)(input)?;
+    let (input, _) = tag(delimiter)(input)?;
+    let (input, _) = tag(delimiter)(input)?;
+    let (input, port) = map_res(digit1, btoi::btou)(input)?;
+    let (input, _) = tag(delimiter)(input)?;
+    tag(")")(input).map(|(input, _)| (input, port))
+}
+
+fn passive_mode(input: &[u8]) -> IResult<&[u8], Addr> {
+    let (input, _) = alt((preceded(take_until("("), tag("(")), take_till(is_digit)))(input)?;
+    let (input, (first, _, second, _, third, _, fourth, _, msb, _, lsb)) = tuple((
+        to_u8,
+        tag(","),
+        to_u8,
+        tag(","),
+        to_u8,
+        tag(","),
+        to_u8,
+        tag(","),
+        to_u8,
+        tag(","),
+        to_u8,
+    ))(input)?;
+
+    Ok((
+        input,
+        Addr::from((
+            [first, second, third, fourth],
+            u16::from(msb) << 8 | u16::from(lsb),
+        )),
+    ))
+}
+
+fn to_u8(input: &[u8]) -> IResult<&[u8], u8> {
+    map_res(take_while_m_n(1, 3, is_digit), btoi::btou)(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{response::Addr, Error};
+
+    #[test]
+    fn test_parse_extended_passive_mode() {
+        assert_eq!(
+            super::parse_extended_passive_mode(b"Entering Extended Passive Mode (|||6446|)")
+                .unwrap(),
+            6446
+        );
+        assert_eq!(
+            super::parse_extended_passive_mode(b"Extended Passive mode OK (!!!65535!)").unwrap(),
+            65535
+        );
+
+        for invalid in [
+            &b"Entering Extended Passive Mode"[..],
+            b"Entering Extended Passive Mode (||6446|)",
+            b"Entering Extended Passive Mode (|||6446)",
+            b"Entering Extended Passive Mode (|||6446!)",
+            b"Entering Extended Passive Mode (|||65536|)",
+            b"Entering Extended Passive Mode (||||)",
+            b"Entering Extended Passive Mode (|1|::1|6446|)",
+        ] {
+            assert!(
+                matches!(
+                    super::parse_extended_passive_mode(invalid),
+                    Err(Error::InvalidExtendedPassiveMode)
+                ),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(super::parse_size(b"1234").unwrap(), 1234);
+        assert_eq!(super::parse_size(b"0 ").unwrap(), 0);
+        assert_eq!(
+            super::parse_size(b"18446744073709551615").unwrap(),
+            u64::MAX
+        );
+
+        for invalid in [&b""[..], b"-1", b"12 bytes", b"18446744073709551616"] {
+            assert!(
+                matches!(super::parse_size(invalid), Err(Error::InvalidNumber(_))),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_pathname() {
+        let cases = [
+            (&br#""/usr/dm" is current directory."#[..], "/usr/dm"),
+            (br#""/""#, "/"),
+            (br#""" is empty"#, ""),
+            (br#"MKD command successful: "/new dir""#, "/new dir"),
+            (br#""/say ""yes""" created"#, r#"/say "yes""#),
+            (br#""""""#, r#"""#),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(super::parse_pathname(input).unwrap(), expected);
+        }
+
+        for invalid in [
+            &b"/usr/dm is current directory"[..],
+            br#""/usr/dm"#,
+            b"\"\xff\"",
+        ] {
+            assert!(
+                matches!(super::parse_pathname(invalid), Err(Error::InvalidPathname)),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_features() {
+        let buf = b"Extensions supported:\n EPRT\n IDLE\n MDTM\n SIZE\n MFMT\n REST STREAM\n MLST type*;size*;sizd*;modify*;UNIX.mode*;UNIX.uid*;UNIX.gid*;unique*;\n MLSD\n AUTH TLS\n PBSZ\n PROT\n UTF8\n TVFS\n ESTA\n PASV\n EPSV\n SPSV\r\nEnd.";
+
+        let feats = super::parse_features(&buf[..]).unwrap();
+
+        let expected = HashMap::from([
+            ("MFMT", None),
+            ("EPSV", None),
+            ("PASV", None),
+            ("ESTA", None),
+            ("AUTH", Some("TLS")),
+            ("REST", Some("STREAM")),
+            ("MLSD", None),
+            ("IDLE", None),
+            ("TVFS", None),
+            ("SPSV", None),
+            ("EPRT", None),
+            ("PBSZ", None),
+            ("MDTM", None),
+            (
+                "MLST",
+                Some("type*;size*;sizd*;modify*;UNIX.mode*;UNIX.uid*;UNIX.gid*;unique*;"),
+            ),
+            ("PROT", None),
+            ("UTF8", None),
+            ("SIZE", None),
+        ]);
+
+        assert_eq!(expected, feats);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_invalid_utf8() {
+        assert!(matches!(
+            super::parse_features(b" \xfe \xff"),
+            Err(Error::InvalidFeature)
+        ));
+    }
+
+    #[test]
+    fn test_parse_passive_mode() {
+        assert_eq!(
+            super::parse_passive_mode(b"Entering Passive Mode (192,168,1,2,255,255)").unwrap(),
+            Addr::from(([192, 168, 1, 2], 65535))
+        );
+        assert_eq!(
+            super::parse_passive_mode(b"Entering Passive Mode 127,0,0,1,4,1").unwrap(),
+            Addr::from(([127, 0, 0, 1], 1025))
+        );
+        assert_eq!(
+            super::parse_passive_mode(b"=127,0,0,1,4,1").unwrap(),
+            Addr::from(([127, 0, 0, 1], 1025))
+        );
+
+        for invalid in [
+            &b"Entering Passive Mode"[..],
+            b"Entering Passive Mode (",
+            b"Entering Passive Mode (127,0,0,1,4)",
+            b"Entering Passive Mode (127,0,0,1,4,256)",
+            b"Entering Passive Mode (127,0,0,1,999,999)",
+            b"Entering Passive Mode (256,0,0,1,4,1)",
+            b"Entering Passive Mode (127,0,0,1,,1)",
+            b"Entering Passive Mode (127.0.0.1.4.1)",
+            b"Entering Passive Mode (127,0,0,1,4|1)",
+        ] {
+            assert!(
+                matches!(
+                    super::parse_passive_mode(invalid),
+                    Err(Error::InvalidPassiveMode)
+                ),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use crate::{DateTime, EntryKind, Error, Result};
+
+/// Maximum number of fields that can precede the name: mode, links, owner,
+/// group, major, minor, month, day and time or year.
+const MAX_FIELDS: usize = 9;
+
+/// A single entry of a Unix `ls -l` style LIST listing, e.g.
+/// `drwxr-xr-x 2 user group 4096 Jan  1 12:00 name`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnixEntry<'a> {
+    pub kind: EntryKind,
+    /// Permission bits including the setuid, setgid and sticky bits.
+    pub mode: u16,
+    pub links: Option<u64>,
+    pub owner: Option<&'a str>,
+    pub group: Option<&'a str>,
+    /// `None` for device files, which report their device numbers instead.
+    pub size: Option<u64>,
+    /// Major and minor number of a block or character device.
+    pub device: Option<(u32, u32)>,
+    pub modified: DateTime,
+    pub name: &'a str,
+    pub target: Option<&'a str>,
+}
+
+/// Parses a single line of a Unix `ls -l` style LIST listing.
+///
+/// Listings only include the year for entries older than roughly six months,
+/// otherwise the year is inferred from `now` such that the entry is not
+/// dated more than a day in the future.
+pub fn parse_unix_entry(line: &str, now: DateTime) -> Result<UnixEntry<'_>> {
+    let fields = Fields::new(line);
+
+    let (kind, mode) = parse_mode(fields.get(0).ok_or(Error::InvalidEntry)?)?;
+
+    // The owner and group columns are optional and may be numeric or named
+    // like a month, so the date is located first and everything else is
+    // anchored on it. It is the last one the columns before it make sense
+    // for, a name that looks like a date only comes after it.
+    let (date, columns) = (2..MAX_FIELDS - 2)
+        .rev()
+        .find_map(|date| Some((date, parse_columns(&fields, date, now).ok()?)))
+        .ok_or(Error::InvalidEntry)?;
+
+    let name_start = fields.start(date + 3).ok_or(Error::InvalidEntry)?;
+    let name = &line[name_start..];
+
+    let (name, target) = match (kind, name.split_once(" -> ")) {
+        (EntryKind::Symlink, Some((name, target))) => (name, Some(target)),
+        _ => (name, None),
+    };
+
+    Ok(UnixEntry {
+        kind,
+        mode,
+        links: columns.links,
+        owner: columns.owner,
+        group: columns.group,
+        size: columns.size,
+        device: columns.device,
+        modified: columns.modified,
+        name,
+        target,
+    })
+}
+
+/// The columns between the mode and the name.
+struct Columns<'a> {
+    links: Option<u64>,
+    owner: Option<&'a str>,
+    group: Option<&'a str>,
+    size: Option<u64>,
+    device: Option<(u32, u32)>,
+    modified: DateTime,
+}
+
+/// Parses the columns up to the end of the date, taking the date to start at
+/// field `date`.
+fn parse_columns<'a>(fields: &Fields<'a>, date: usize, now: DateTime) -> Result<Columns<'a>> {
+    let modified = parse_date(
+        fields.get(date).ok_or(Error::InvalidEntry)?,
+        fields.get(date + 1).ok_or(Error::InvalidEntry)?,
+        fields.get(date + 2).ok_or(Error::InvalidEntry)?,
+        now,
+    )?;
+
+    let mut size_idx = date - 1;
+    let (size, device) = match (fields.get(size_idx - 1), fields.get(size_idx)) {
+        // `1, 3` or `1,3`
+        (Some(major), Some(minor)) if major.ends_with(',') => {
+            size_idx -= 1;
+            let major = btoi::btou(major.trim_end_matches(',').as_bytes())?;
+            (None, Some((major, btoi::btou(minor.as_bytes())?)))
+        }
+        (_, Some(size)) => match size.split_once(',') {
+            Some((major, minor)) => (
+                None,
+                Some((btoi::btou(major.as_bytes())?, btoi::btou(minor.as_bytes())?)),
+            ),
+            None => (Some(btoi::btou(size.as_bytes())?), None),
+        },
+        _ => return Err(Error::InvalidEntry),
+    };
+
+    let (links, owner, group) = match size_idx {
+        1 => (None, None, None),
+        2 => match fields.get(1) {
+            Some(field) => match btoi::btou(field.as_bytes()) {
+                Ok(links) => (Some(links), None, None),
+                Err(_) => (None, Some(field), None),
+            },
+            None => return Err(Error::InvalidEntry),
+        },
+        3 => match (fields.get(1), fields.get(2)) {
+            (Some(first), Some(second)) => match btoi::btou(first.as_bytes()) {
+                Ok(links) => (Some(links), Some(second), None),
+                Err(_) => (None, Some(first), Some(second)),
+            },
+            _ => return Err(Error::InvalidEntry),
+        },
+        4 => (
+            Some(btoi::btou(
+                fields.get(1).ok_or(Error::InvalidEntry)?.as_bytes(),
+            )?),
+            fields.get(2),
+            fields.get(3),
+        ),
+        _ => return Err(Error::InvalidEntry),
+    };
+
+    Ok(Columns {
+        links,
+        owner,
+        group,
+        size,
+        device,
+        modified,
+    })
+}
+
+/// Start and end offsets of the leading whitespace separated fields.
+struct Fields<'a> {
+    line: &'a str,
+    offsets: [(usize, usize); MAX_FIELDS + 1],
+    len: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: &'a str) -> Self {
+        let mut fields = Self {
+            line,
+            offsets: [(0, 0); MAX_FIELDS + 1],
+            len: 0,
+        };
+
+        let bytes = line.as_bytes();
+        let mut pos = 0;
+
+        while fields.len < fields.offsets.len() {
+            while pos < bytes.len() && bytes[pos] == b' ' {
+                pos += 1;
+            }
+
+            if pos == bytes.len() {
+                break;
+            }
+
+            let start = pos;
+
+            while pos < bytes.len() && bytes[pos] != b' ' {
+                pos += 1;
+            }
+
+            fields.offsets[fields.len] = (start, pos);
+            fields.len += 1;
+        }
+
+        fields
+    }
+
+    fn get(&self, idx: usize) -> Option<&'a str> {
+        if idx < self.len {
+            let (start, end) = self.offsets[idx];
+            Some(&self.line[start..end])
+        } else {
+            None
+        }
+    }
+
+    fn start(&self, idx: usize) -> Option<usize> {
+        if idx < self.len {
+            Some(self.offsets[idx].0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a mode like `drwxr-sr-t`, ignoring any trailing ACL or extended
+/// attribute marker such as `+` or `@`.
+fn parse_mode(field: &str) -> Result<(EntryKind, u16)> {
+    let bytes = field.as_bytes();
+
+    if bytes.len() < 10 {
+        return Err(Error::InvalidEntry);
+    }
+
+    let kind = match bytes[0] {
+        b'-' => EntryKind::File,
+        b'd' => EntryKind::Directory,
+        b'l' => EntryKind::Symlink,
+        b'b' | b'c' | b'p' | b's' | b'D' => EntryKind::Other,
+        _ => return Err(Error::InvalidEntry),
+    };
+
+    let mut mode = 0;
+
+    for (idx, symbol) in bytes[1..10].iter().enumerate() {
+        let bit = 1 << (8 - idx);
+
+        // The special bits replace the execute bit of their class
+        mode |= match (idx, symbol) {
+            (_, b'-') => 0,
+            (0 | 3 | 6, b'r') | (1 | 4 | 7, b'w') | (2 | 5 | 8, b'x') => bit,
+            (2, b's') => bit | 0o4000,
+            (2, b'S') => 0o4000,
+            (5, b's') => bit | 0o2000,
+            (5, b'S') => 0o2000,
+            (8, b't') => bit | 0o1000,
+            (8, b'T') => 0o1000,
+            _ => return Err(Error::InvalidEntry),
+        };
+    }
+
+    Ok((kind, mode))
+}
+
+fn parse_date(month: &str, day: &str, year_or_time: &str, now: DateTime) -> Result<DateTime> {
+    let month = month_number(month).ok_or(Error::InvalidEntry)?;
+    let day = btoi::btou(day.as_bytes())?;
+
+    if !(1..=31).contains(&day) {
+        return Err(Error::InvalidEntry);
+    }
+
+    let datetime = match year_or_time.split_once(':') {
+        Some((hour, minute)) => {
+            let hour = btoi::btou(hour.as_bytes())?;
+            let minute = btoi::btou(minute.as_bytes())?;
+
+            if hour > 23 || minute > 59 {
+                return Err(Error::InvalidEntry);
+            }
+
+            // Allow a day of slack for servers whose local time is ahead of us.
+            let year = if (month, day) > (now.month, now.day + 1) {
+                now.year.saturating_sub(1)
+            } else {
+                now.year
+            };
+
+            DateTime {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                ..DateTime::default()
+            }
+        }
+        None => DateTime {
+            year: btoi::btou(year_or_time.as_bytes())?,
+            month,
+            day,
+            ..DateTime::default()
+        },
+    };
+
+    Ok(datetime)
+}
+
+fn month_number(month: &str) -> Option<u8> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+
+    MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(month))
+        .map(|idx| idx as u8 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: DateTime = DateTime {
+        year: 2023,
+        month: 6,
+        day: 15,
+        hour: 10,
+        minute: 0,
+        second: 0,
+        nanosecond: 0,
+    };
+
+    fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            ..DateTime::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let entry = parse_unix_entry(
+            "-rw-r--r--   1 user  group   4096 Jan  5  2021 notes.txt",
+            NOW,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entry,
+            UnixEntry {
+                kind: EntryKind::File,
+                mode: 0o644,
+                links: Some(1),
+                owner: Some("user"),
+                group: Some("group"),
+                size: Some(4096),
+                device: None,
+                modified: date(2021, 1, 5, 0, 0),
+                name: "notes.txt",
+                target: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        let entry = parse_unix_entry("drwxr-xr-x 2 1000 1000 4096 Mar 1 12:30 src", NOW).unwrap();
+
+        assert_eq!(entry.kind, EntryKind::Directory);
+        assert_eq!(entry.mode, 0o755);
+        assert_eq!(entry.links, Some(2));
+        assert_eq!(entry.owner, Some("1000"));
+        assert_eq!(entry.group, Some("1000"));
+        assert_eq!(entry.modified, date(2023, 3, 1, 12, 30));
+        assert_eq!(entry.name, "src");
+    }
+
+    #[test]
+    fn test_parse_names_with_spaces() {
+        let entry = parse_unix_entry(
+            "-rw-r--r-- 1 user group 10 Jun 15 09:00 my  holiday photo.jpg ",
+            NOW,
+        )
+        .unwrap();
+
+        assert_eq!(entry.name, "my  holiday photo.jpg ");
+
+        // A name that looks like a date must not confuse the parser
+        let entry =
+            parse_unix_entry("-rw-r--r-- 1 user group 10 Jun 15 2020 Jan 1 2020", NOW).unwrap();
+
+        assert_eq!(entry.modified, date(2020, 6, 15, 0, 0));
+        assert_eq!(entry.name, "Jan 1 2020");
+    }
+
+    #[test]
+    fn test_parse_month_like_owner() {
+        let entry =
+            parse_unix_entry("-rw-r--r-- 1 may 12 4096 Jan  1  2020 notes.txt", NOW).unwrap();
+
+        assert_eq!(entry.links, Some(1));
+        assert_eq!(entry.owner, Some("may"));
+        assert_eq!(entry.group, Some("12"));
+        assert_eq!(entry.size, Some(4096));
+        assert_eq!(entry.modified, date(2020, 1, 1, 0, 0));
+        assert_eq!(entry.name, "notes.txt");
+
+        let entry = parse_unix_entry("-rw-r--r-- 1 user jan 5 Mar 1 12:30 a", NOW).unwrap();
+
+        assert_eq!(entry.owner, Some("user"));
+        assert_eq!(entry.group, Some("jan"));
+        assert_eq!(entry.size, Some(5));
+        assert_eq!(entry.modified, date(2023, 3, 1, 12, 30));
+        assert_eq!(entry.name, "a");
+
+        let entry = parse_unix_entry("-rw-r--r-- 1 jan 1 2020 Jan 1 2020 a", NOW).unwrap();
+
+        assert_eq!(entry.owner, Some("jan"));
+        assert_eq!(entry.group, Some("1"));
+        assert_eq!(entry.size, Some(2020));
+        assert_eq!(entry.name, "a");
+    }
+
+    #[test]
+    fn test_parse_symlink() {
+        let entry = parse_unix_entry(
+            "lrwxrwxrwx 1 root root 11 Feb 2 2022 my link -> /etc/my target",
+            NOW,
+        )
+        .unwrap();
+
+        assert_eq!(entry.kind, EntryKind::Symlink);
+        assert_eq!(entry.mode, 0o777);
+        assert_eq!(entry.name, "my link");
+        assert_eq!(entry.target, Some("/etc/my target"));
+
+        // Only symlinks are split
+        let entry = parse_unix_entry("-rw-r--r-- 1 u g 1 Feb 2 2022 a -> b", NOW).unwrap();
+
+        assert_eq!(entry.name, "a -> b");
+        assert_eq!(entry.target, None);
+    }
+
+    #[test]
+    fn test_parse_device_files() {
+        let entry =
+            parse_unix_entry("crw-rw-rw- 1 root root   1,   3 Jan 1 2020 null", NOW).unwrap();
+
+        assert_eq!(entry.kind, EntryKind::Other);
+        assert_eq!(entry.owner, Some("root"));
+        assert_eq!(entry.group, Some("root"));
+        assert_eq!(entry.size, None);
+        assert_eq!(entry.device, Some((1, 3)));
+        assert_eq!(entry.name, "null");
+
+        let entry = parse_unix_entry("brw-rw---- 1 root disk 8,0 Jan 1 2020 sda", NOW).unwrap();
+
+        assert_eq!(entry.device, Some((8, 0)));
+        assert_eq!(entry.group, Some("disk"));
+    }
+
+    #[test]
+    fn test_parse_missing_columns() {
+        // No group
+        let entry = parse_unix_entry("-rw-r--r-- 1 user 12 Jan 1 2020 a", NOW).unwrap();
+
+        assert_eq!(entry.links, Some(1));
+        assert_eq!(entry.owner, Some("user"));
+        assert_eq!(entry.group, None);
+        assert_eq!(entry.size, Some(12));
+
+        // No link count
+        let entry = parse_unix_entry("-rw-r--r-- user group 12 Jan 1 2020 a", NOW).unwrap();
+
+        assert_eq!(entry.links, None);
+        assert_eq!(entry.owner, Some("user"));
+        assert_eq!(entry.group, Some("group"));
+
+        // Only a size, as sent by some embedded servers
+        let entry = parse_unix_entry("-rw-r--r-- 1 12 Jan 1 2020 a", NOW).unwrap();
+
+        assert_eq!(entry.links, Some(1));
+        assert_eq!(entry.owner, None);
+        assert_eq!(entry.size, Some(12));
+    }
+
+    #[test]
+    fn test_parse_special_permissions() {
+        let cases = [
+            ("-rwsr-xr-x", 0o4755),
+            ("-rwSr--r--", 0o4644),
+            ("-rwxr-sr-x", 0o2755),
+            ("-rw-r-Sr--", 0o2644),
+            ("drwxrwxrwt", 0o1777),
+            ("drwxrwxrwT", 0o1776),
+            ("-rw-r--r--+", 0o644),
+            ("-rw-r--r--@", 0o644),
+            ("-rw-r--r--.", 0o644),
+            ("prw-------", 0o600),
+            ("srwxrwxrwx", 0o777),
+        ];
+
+        for (mode, expected) in cases {
+            let line = format!("{} 1 u g 0 Jan 1 2020 x", mode);
+
+            assert_eq!(
+                parse_unix_entry(&line, NOW).unwrap().mode,
+                expected,
+                "{}",
+                mode
+            );
+        }
+
+        for mode in ["-rw-r--r", "xrw-r--r--", "-rw-r--r-s", "-wr-r--r--"] {
+            let line = format!("{} 1 u g 0 Jan 1 2020 x", mode);
+
+            assert!(parse_unix_entry(&line, NOW).is_err(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn test_year_inference() {
+        let parse = |date: &str, now| {
+            parse_unix_entry(&format!("-rw-r--r-- 1 u g 0 {} x", date), now)
+                .unwrap()
+                .modified
+        };
+
+        assert_eq!(parse("Jun 15 09:00", NOW), date(2023, 6, 15, 9, 0));
+        assert_eq!(parse("Jan  1 00:00", NOW), date(2023, 1, 1, 0, 0));
+        assert_eq!(parse("Jun 16 23:59", NOW), date(2023, 6, 16, 23, 59));
+        assert_eq!(parse("Jun 17 00:00", NOW), date(2022, 6, 17, 0, 0));
+        assert_eq!(parse("Dec 31 23:59", NOW), date(2022, 12, 31, 23, 59));
+
+        let new_year = DateTime {
+            year: 2024,
+            month: 1,
+            day: 2,
+            ..DateTime::default()
+        };
+
+        assert_eq!(parse("Dec 30 10:00", new_year), date(2023, 12, 30, 10, 0));
+        assert_eq!(parse("Jan  2 10:00", new_year), date(2024, 1, 2, 10, 0));
+        assert_eq!(parse("JAN  2 10:00", new_year), date(2024, 1, 2, 10, 0));
+    }
+
+    #[test]
+    fn test_parse_invalid_lines() {
+        for line in [
+            "",
+            "total 12",
+            "-rw-r--r-- 1 u g 0 Jan 1 2020",
+            "-rw-r--r-- 1 u g 0 Foo 1 2020 x",
+            "-rw-r--r-- 1 u g 0 Jan 32 2020 x",
+            "-rw-r--r-- 1 u g 0 Jan 1 24:00 x",
+            "-rw-r--r-- 1 u g big Jan 1 2020 x",
+            "-rw-r--r-- 1 u g 0 Jan 1 20x0 x",
+            "-rw-r--r-- Jan 1 2020 x",
+        ] {
+            assert!(parse_unix_entry(line, NOW).is_err(), "{:?}", line);
+        }
+    }
+}
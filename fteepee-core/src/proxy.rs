@@ -0,0 +1,540 @@
+//! Messages for connecting to the server through a [`Proxy`].
+//!
+//! Like [`Session`](crate::session::Session) none of this does any I/O, the
+//! clients send the requests built here and read the replies into the
+//! parsers.
+
+use core::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+};
+use std::{io, string::String};
+
+/// A proxy the control connection and passive data connections are made
+/// through.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Proxy {
+    pub kind: ProxyKind,
+    /// Where the proxy is, as `host:port`.
+    pub addr: String,
+    pub credentials: Option<Credentials>,
+}
+
+impl Proxy {
+    pub fn socks5(addr: impl Into<String>) -> Self {
+        Self {
+            kind: ProxyKind::Socks5,
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    pub fn http(addr: impl Into<String>) -> Self {
+        Self {
+            kind: ProxyKind::Http,
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticates with the proxy, using username/password authentication
+    /// for SOCKS5 and basic authentication for HTTP.
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some(Credentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProxyKind {
+    /// [RFC 1928](https://www.rfc-editor.org/rfc/rfc1928), with
+    /// [RFC 1929](https://www.rfc-editor.org/rfc/rfc1929) authentication.
+    Socks5,
+    /// An HTTP proxy that supports the CONNECT method, see
+    /// [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#name-connect).
+    Http,
+}
+
+/// Where to connect to. A host name is only resolved by whatever makes the
+/// connection, which is the proxy if there is one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    Addr(SocketAddr),
+    Host(String, u16),
+}
+
+impl Target {
+    pub fn port(&self) -> u16 {
+        match self {
+            Target::Addr(addr) => addr.port(),
+            Target::Host(_, port) => *port,
+        }
+    }
+}
+
+/// Formats as `host:port`, with IPv6 addresses in brackets.
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Addr(addr) => addr.fmt(f),
+            Target::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// Converts to a [`Target`], implemented for the same types as
+/// [`ToSocketAddrs`](std::net::ToSocketAddrs) but without resolving any
+/// host names.
+pub trait ToTarget {
+    fn to_target(&self) -> io::Result<Target>;
+}
+
+impl ToTarget for Target {
+    fn to_target(&self) -> io::Result<Target> {
+        Ok(self.clone())
+    }
+}
+
+impl ToTarget for SocketAddr {
+    fn to_target(&self) -> io::Result<Target> {
+        Ok(Target::Addr(*self))
+    }
+}
+
+impl ToTarget for SocketAddrV4 {
+    fn to_target(&self) -> io::Result<Target> {
+        SocketAddr::V4(*self).to_target()
+    }
+}
+
+impl ToTarget for SocketAddrV6 {
+    fn to_target(&self) -> io::Result<Target> {
+        SocketAddr::V6(*self).to_target()
+    }
+}
+
+impl ToTarget for (IpAddr, u16) {
+    fn to_target(&self) -> io::Result<Target> {
+        SocketAddr::from(*self).to_target()
+    }
+}
+
+impl ToTarget for (Ipv4Addr, u16) {
+    fn to_target(&self) -> io::Result<Target> {
+        SocketAddr::from(*self).to_target()
+    }
+}
+
+impl ToTarget for (Ipv6Addr, u16) {
+    fn to_target(&self) -> io::Result<Target> {
+        SocketAddr::from(*self).to_target()
+    }
+}
+
+impl ToTarget for (&str, u16) {
+    fn to_target(&self) -> io::Result<Target> {
+        let (host, port) = *self;
+
+        match host.parse::<IpAddr>() {
+            Ok(ip) => (ip, port).to_target(),
+            Err(_) => Ok(Target::Host(host.into(), port)),
+        }
+    }
+}
+
+impl ToTarget for (String, u16) {
+    fn to_target(&self) -> io::Result<Target> {
+        (self.0.as_str(), self.1).to_target()
+    }
+}
+
+/// Parses `host:port`, where an IPv6 address has to be in brackets.
+impl ToTarget for str {
+    fn to_target(&self) -> io::Result<Target> {
+        if let Ok(addr) = self.parse::<SocketAddr>() {
+            return addr.to_target();
+        }
+
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+        let (host, port) = self
+            .rsplit_once(':')
+            .ok_or_else(|| invalid("invalid socket address"))?;
+        let port = port.parse().map_err(|_| invalid("invalid port value"))?;
+
+        (host, port).to_target()
+    }
+}
+
+impl ToTarget for String {
+    fn to_target(&self) -> io::Result<Target> {
+        self.as_str().to_target()
+    }
+}
+
+impl<T: ToTarget + ?Sized> ToTarget for &T {
+    fn to_target(&self) -> io::Result<Target> {
+        (**self).to_target()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"****")
+            .finish()
+    }
+}
+
+pub mod socks5 {
+    use core::net::IpAddr;
+    use std::{vec, vec::Vec};
+
+    use super::{Credentials, Target};
+    use crate::{Error, ProxyError, Result};
+
+    const VERSION: u8 = 5;
+    const AUTH_VERSION: u8 = 1;
+    const NO_AUTH: u8 = 0;
+    const USERNAME_PASSWORD: u8 = 2;
+    const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+    const CONNECT: u8 = 1;
+    const IPV4: u8 = 1;
+    const DOMAIN: u8 = 3;
+    const IPV6: u8 = 4;
+
+    /// Offers username/password authentication if there are `credentials`.
+    pub fn greeting(credentials: Option<&Credentials>) -> Vec<u8> {
+        match credentials {
+            Some(_) => vec![VERSION, 2, NO_AUTH, USERNAME_PASSWORD],
+            None => vec![VERSION, 1, NO_AUTH],
+        }
+    }
+
+    /// Parses the method the proxy chose, returning whether it wants a
+    /// username and password.
+    pub fn parse_method(reply: [u8; 2]) -> Result<bool> {
+        match reply {
+            [VERSION, NO_AUTH] => Ok(false),
+            [VERSION, USERNAME_PASSWORD] => Ok(true),
+            [VERSION, NO_ACCEPTABLE_METHODS] => Err(Error::Proxy(ProxyError::AuthFailed)),
+            _ => Err(Error::Proxy(ProxyError::InvalidReply)),
+        }
+    }
+
+    pub fn auth_request(credentials: &Credentials) -> Result<Vec<u8>> {
+        let username = credentials.username.as_bytes();
+        let password = credentials.password.as_bytes();
+
+        // Each is sent after its length as a single byte
+        let too_long = |_| Error::Proxy(ProxyError::CredentialsTooLong);
+        let username_len = u8::try_from(username.len()).map_err(too_long)?;
+        let password_len = u8::try_from(password.len()).map_err(too_long)?;
+
+        let mut request = vec![AUTH_VERSION, username_len];
+        request.extend_from_slice(username);
+        request.push(password_len);
+        request.extend_from_slice(password);
+
+        Ok(request)
+    }
+
+    pub fn parse_auth(reply: [u8; 2]) -> Result<()> {
+        match reply {
+            [AUTH_VERSION, 0] => Ok(()),
+            [AUTH_VERSION, _] => Err(Error::Proxy(ProxyError::AuthFailed)),
+            _ => Err(Error::Proxy(ProxyError::InvalidReply)),
+        }
+    }
+
+    /// Asks the proxy to connect to `target`, a host name is left for the
+    /// proxy to resolve.
+    pub fn connect_request(target: &Target) -> Result<Vec<u8>> {
+        let mut request = vec![VERSION, CONNECT, 0];
+
+        match target {
+            Target::Addr(addr) => match addr.ip() {
+                IpAddr::V4(ip) => {
+                    request.push(IPV4);
+                    request.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    request.push(IPV6);
+                    request.extend_from_slice(&ip.octets());
+                }
+            },
+            Target::Host(host, _) => {
+                // Sent after its length as a single byte
+                let len =
+                    u8::try_from(host.len()).map_err(|_| Error::Proxy(ProxyError::HostTooLong))?;
+
+                request.extend_from_slice(&[DOMAIN, len]);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+
+        request.extend_from_slice(&target.port().to_be_bytes());
+        Ok(request)
+    }
+
+    /// Parses the start of the reply to a connect request, up to the first
+    /// byte of the address the proxy bound. Returns how many bytes of the
+    /// reply are left, which can be discarded.
+    pub fn parse_connect(reply: [u8; 5]) -> Result<usize> {
+        let [version, status, _, kind, first] = reply;
+
+        if version != VERSION {
+            return Err(Error::Proxy(ProxyError::InvalidReply));
+        }
+
+        if status != 0 {
+            return Err(Error::Proxy(ProxyError::Socks5(status)));
+        }
+
+        // The address, followed by a port
+        match kind {
+            IPV4 => Ok(4 - 1 + 2),
+            IPV6 => Ok(16 - 1 + 2),
+            // The first byte is the length of the domain
+            DOMAIN => Ok(usize::from(first) + 2),
+            _ => Err(Error::Proxy(ProxyError::InvalidReply)),
+        }
+    }
+}
+
+pub mod http {
+    use std::{format, vec::Vec};
+
+    use super::{base64, Credentials, Target};
+    use crate::{Error, ProxyError, Result};
+
+    /// The most bytes read of the proxy's response before giving up on it.
+    pub const MAX_RESPONSE_SIZE: usize = 8 * 1024;
+
+    /// Asks the proxy to connect to `target`, a host name is left for the
+    /// proxy to resolve.
+    pub fn connect_request(target: &Target, credentials: Option<&Credentials>) -> Vec<u8> {
+        let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+
+        if let Some(credentials) = credentials {
+            let basic = format!("{}:{}", credentials.username, credentials.password);
+            request.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64(basic.as_bytes())
+            ));
+        }
+
+        request.push_str("\r\n");
+        request.into_bytes()
+    }
+
+    /// Whether `response` holds the whole head of the response, which ends
+    /// with an empty line. Nothing after it should be read as it comes from
+    /// the server.
+    pub fn is_complete(response: &[u8]) -> bool {
+        response.ends_with(b"\r\n\r\n")
+    }
+
+    /// Parses the head of the proxy's response, any 2xx status means the
+    /// connection is open.
+    pub fn parse_response(response: &[u8]) -> Result<()> {
+        let status = response
+            .strip_prefix(b"HTTP/1.")
+            .and_then(|rest| rest.get(1..5))
+            .and_then(|status| match status {
+                [b' ', digits @ ..] => btoi::btou::<u16>(digits).ok(),
+                _ => None,
+            })
+            .ok_or(Error::Proxy(ProxyError::InvalidReply))?;
+
+        match status {
+            200..=299 => Ok(()),
+            407 => Err(Error::Proxy(ProxyError::AuthFailed)),
+            status => Err(Error::Proxy(ProxyError::Http(status))),
+        }
+    }
+}
+
+/// Encodes `bytes` with the standard alphabet and padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - i * 8)
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - i * 6)) & 0x3f;
+                encoded.push(char::from(ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::SocketAddr;
+
+    use super::{base64, http, socks5, Proxy, Target, ToTarget};
+    use crate::{Error, ProxyError};
+
+    #[test]
+    fn test_socks5() {
+        let proxy = Proxy::socks5("proxy:1080").credentials("user", "pass");
+        let credentials = proxy.credentials.as_ref();
+
+        assert_eq!(socks5::greeting(None), [5, 1, 0]);
+        assert_eq!(socks5::greeting(credentials), [5, 2, 0, 2]);
+
+        assert!(!socks5::parse_method([5, 0]).unwrap());
+        assert!(socks5::parse_method([5, 2]).unwrap());
+        assert!(matches!(
+            socks5::parse_method([5, 0xff]),
+            Err(Error::Proxy(ProxyError::AuthFailed))
+        ));
+        assert!(matches!(
+            socks5::parse_method([4, 0]),
+            Err(Error::Proxy(ProxyError::InvalidReply))
+        ));
+
+        assert_eq!(
+            socks5::auth_request(credentials.unwrap()).unwrap(),
+            b"\x01\x04user\x04pass"
+        );
+        assert!(socks5::parse_auth([1, 0]).is_ok());
+        assert!(matches!(
+            socks5::parse_auth([1, 1]),
+            Err(Error::Proxy(ProxyError::AuthFailed))
+        ));
+
+        let long = Proxy::socks5("proxy:1080").credentials(&"a".repeat(256), "pass");
+        assert!(matches!(
+            socks5::auth_request(long.credentials.as_ref().unwrap()),
+            Err(Error::Proxy(ProxyError::CredentialsTooLong))
+        ));
+
+        assert_eq!(
+            socks5::connect_request(&Target::Addr(SocketAddr::from(([192, 0, 2, 1], 21)))).unwrap(),
+            [5, 1, 0, 1, 192, 0, 2, 1, 0, 21]
+        );
+        assert_eq!(
+            socks5::connect_request(&Target::Addr("[::1]:2121".parse().unwrap())).unwrap(),
+            [5, 1, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 8, 73]
+        );
+        assert_eq!(
+            socks5::connect_request(&Target::Host("ftp.example".into(), 21)).unwrap(),
+            b"\x05\x01\x00\x03\x0bftp.example\x00\x15"
+        );
+        assert!(matches!(
+            socks5::connect_request(&Target::Host("a".repeat(256), 21)),
+            Err(Error::Proxy(ProxyError::HostTooLong))
+        ));
+
+        assert_eq!(socks5::parse_connect([5, 0, 0, 1, 10]).unwrap(), 5);
+        assert_eq!(socks5::parse_connect([5, 0, 0, 4, 0]).unwrap(), 17);
+        assert_eq!(socks5::parse_connect([5, 0, 0, 3, 9]).unwrap(), 11);
+        assert!(matches!(
+            socks5::parse_connect([5, 5, 0, 1, 0]),
+            Err(Error::Proxy(ProxyError::Socks5(5)))
+        ));
+    }
+
+    #[test]
+    fn test_http() {
+        let addr = Target::Addr(SocketAddr::from(([192, 0, 2, 1], 21)));
+        let proxy = Proxy::http("proxy:3128").credentials("user", "pass");
+
+        assert_eq!(
+            http::connect_request(&addr, None),
+            b"CONNECT 192.0.2.1:21 HTTP/1.1\r\nHost: 192.0.2.1:21\r\n\r\n"
+        );
+        assert_eq!(
+            http::connect_request(&Target::Addr("[::1]:21".parse().unwrap()), None),
+            b"CONNECT [::1]:21 HTTP/1.1\r\nHost: [::1]:21\r\n\r\n"
+        );
+        assert_eq!(
+            http::connect_request(&Target::Host("ftp.example".into(), 21), None),
+            b"CONNECT ftp.example:21 HTTP/1.1\r\nHost: ftp.example:21\r\n\r\n"
+        );
+        assert_eq!(
+            http::connect_request(&addr, proxy.credentials.as_ref()),
+            &b"CONNECT 192.0.2.1:21 HTTP/1.1\r\nHost: 192.0.2.1:21\r\n\
+               Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"[..]
+        );
+
+        assert!(!http::is_complete(b"HTTP/1.1 200 OK\r\n"));
+        assert!(http::is_complete(b"HTTP/1.1 200 OK\r\n\r\n"));
+
+        assert!(http::parse_response(b"HTTP/1.1 200 Connection established\r\n\r\n").is_ok());
+        assert!(http::parse_response(b"HTTP/1.0 200\r\n\r\n").is_ok());
+        assert!(matches!(
+            http::parse_response(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n"),
+            Err(Error::Proxy(ProxyError::AuthFailed))
+        ));
+        assert!(matches!(
+            http::parse_response(b"HTTP/1.1 502 Bad Gateway\r\n\r\n"),
+            Err(Error::Proxy(ProxyError::Http(502)))
+        ));
+        assert!(matches!(
+            http::parse_response(b"220 Ready\r\n"),
+            Err(Error::Proxy(ProxyError::InvalidReply))
+        ));
+    }
+
+    #[test]
+    fn test_to_target() {
+        let addr = Target::Addr(SocketAddr::from(([192, 0, 2, 1], 21)));
+        let host = Target::Host("ftp.example".into(), 21);
+
+        assert_eq!("192.0.2.1:21".to_target().unwrap(), addr);
+        assert_eq!(("192.0.2.1", 21).to_target().unwrap(), addr);
+        assert_eq!(
+            "[::1]:21".to_target().unwrap(),
+            Target::Addr("[::1]:21".parse().unwrap())
+        );
+        assert_eq!(
+            ("::1", 21).to_target().unwrap(),
+            Target::Addr("[::1]:21".parse().unwrap())
+        );
+        assert_eq!("ftp.example:21".to_target().unwrap(), host);
+        assert_eq!(("ftp.example", 21).to_target().unwrap(), host);
+        assert_eq!((String::from("ftp.example"), 21).to_target().unwrap(), host);
+
+        assert!("ftp.example".to_target().is_err());
+        assert!("ftp.example:ftp".to_target().is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_credentials_debug() {
+        let proxy = Proxy::socks5("proxy:1080").credentials("user", "secret");
+
+        assert!(!format!("{:?}", proxy).contains("secret"));
+    }
+}
@@ -0,0 +1,66 @@
+use core::fmt;
+use std::{string::String, vec::Vec};
+
+use crate::Code;
+
+/// A complete reply from the server, e.g. `550 Permission denied`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reply {
+    pub code: Code,
+    /// The text of each line, without the code.
+    pub lines: Vec<String>,
+}
+
+impl Reply {
+    pub fn new(code: Code, lines: Vec<String>) -> Self {
+        Self { code, lines }
+    }
+
+    /// Creates a reply from a message parsed by
+    /// [`Response`](crate::response::Response), whose lines are joined by
+    /// LF. Invalid UTF-8 is replaced.
+    pub fn from_message(code: Code, message: &[u8]) -> Self {
+        let lines = message
+            .split(|byte| *byte == b'\n')
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect();
+
+        Self::new(code, lines)
+    }
+
+    /// The text of the reply with its lines joined by LF.
+    pub fn message(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code.as_u16(), self.lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reply;
+    use crate::Code;
+
+    #[test]
+    fn test_from_message() {
+        let reply = Reply::from_message(Code::SYSTEM_STATUS, b"Features:\n EPSV\nEnd");
+
+        assert_eq!(reply.code, Code::SYSTEM_STATUS);
+        assert_eq!(reply.lines, ["Features:", " EPSV", "End"]);
+        assert_eq!(reply.message(), "Features:\n EPSV\nEnd");
+
+        let reply = Reply::from_message(Code::FILE_UNAVAILABLE, b"");
+        assert_eq!(reply.lines, [""]);
+    }
+
+    #[test]
+    fn test_display() {
+        let reply = Reply::from_message(Code::FILE_UNAVAILABLE, b"Permission denied");
+
+        assert_eq!(reply.to_string(), "550 Permission denied");
+    }
+}
@@ -1,93 +1,234 @@
-use crate::{parsers::parse_passive_mode, Code, Error, Result};
-
-pub type Addr = ([u8; 4], u16);
-
-const NEWLINE: &[u8] = b"\n";
+use crate::{
+    parsers::{parse_extended_passive_mode, parse_passive_mode, parse_size},
+    Code, Error, Result,
+};
+
+pub type Addr = core::net::SocketAddr;
+
+/// Progress made by [`Response::parse`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The reply was completed by the first `n` bytes of the input, anything
+    /// after them belongs to the next reply.
+    Complete(usize),
+    /// The first `n` bytes of the input were consumed without completing the
+    /// reply. Less than all of the input is only consumed when the output is
+    /// full, parsing can carry on with the rest once it has been grown.
+    Partial(usize),
+}
 
-#[derive(Clone, Copy, Debug)]
-pub enum ParsedResponseState {
-    Empty,
-    Partial,
-    Complete,
+/// Bounds on the size of a reply, so that a server cannot keep a reply going
+/// forever.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// The longest message allowed, in bytes.
+    pub max_size: usize,
+    /// The most lines a multiline reply may have.
+    pub max_lines: usize,
 }
 
-impl Default for ParsedResponseState {
+impl Default for Limits {
     fn default() -> Self {
-        Self::Empty
+        Self {
+            max_size: 64 * 1024,
+            max_lines: 1024,
+        }
     }
 }
 
+/// Parses a reply from chunks of bytes as they are read, they can be split
+/// anywhere and lines can end with either CRLF or LF.
+///
+/// The message is written to an output buffer that has to be passed to
+/// every call, multiline messages have their lines joined by LF. As in
+/// [RFC 959](https://www.rfc-editor.org/rfc/rfc959#page-36) a multiline
+/// reply ends with a line starting with its code followed by a space, lines
+/// in between may start with the code followed by a hyphen, which is
+/// stripped.
+///
+/// Replies that go over the parser's [`Limits`] fail with
+/// [`Error::ReplyTooLarge`].
 pub struct Response {
     code: [u8; 3],
-    state: ParsedResponseState,
+    state: State,
+    /// The start of the current line, until it is known whether it starts
+    /// with the reply code.
+    prefix: [u8; 4],
+    prefix_len: usize,
+    carriage_return: bool,
     cursor: usize,
+    /// Lines of the message so far, less one.
+    newlines: usize,
+    limits: Limits,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Code,
+    Text { last: bool },
+    Prefix,
+    Complete,
 }
 
 impl Response {
     pub fn new() -> Self {
+        Self::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
         Self {
             code: [0; 3],
-            state: ParsedResponseState::default(),
+            state: State::Code,
+            prefix: [0; 4],
+            prefix_len: 0,
+            carriage_return: false,
             cursor: 0,
+            newlines: 0,
+            limits,
         }
     }
 
+    /// Changes the limits, including for the part of the reply that has
+    /// already been parsed.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
     pub fn code(&self) -> Result<Code> {
         Code::try_from(self.code)
     }
 
-    pub fn message<'a>(&self, input: &'a [u8]) -> &'a [u8] {
-        &input[..self.cursor]
+    pub fn message<'a>(&self, output: &'a [u8]) -> &'a [u8] {
+        &output[..self.cursor]
+    }
+
+    /// Parses as much of `input` as possible, writing the message to
+    /// `output`.
+    pub fn parse(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status> {
+        if let State::Complete = self.state {
+            return Ok(Status::Complete(0));
+        }
+
+        for (idx, byte) in input.iter().enumerate() {
+            // A carriage return is only part of the message when it does not
+            // end the line
+            if core::mem::take(&mut self.carriage_return)
+                && *byte != b'\n'
+                && !self.feed(b'\r', output)?
+            {
+                self.carriage_return = true;
+                return Ok(Status::Partial(idx));
+            }
+
+            match byte {
+                b'\r' => self.carriage_return = true,
+                byte if !self.feed(*byte, output)? => return Ok(Status::Partial(idx)),
+                _ => {}
+            }
+
+            if let State::Complete = self.state {
+                return Ok(Status::Complete(idx + 1));
+            }
+        }
+
+        Ok(Status::Partial(input.len()))
     }
 
-    pub fn read_bytes(
-        &mut self,
-        input: &[u8],
-        mut output: &mut [u8],
-    ) -> Result<ParsedResponseState> {
+    /// Returns `false` if `byte` did not fit in `output`, leaving the state
+    /// as it was.
+    fn feed(&mut self, byte: u8, output: &mut [u8]) -> Result<bool> {
         match self.state {
-            ParsedResponseState::Empty => match input {
-                [f @ b'0'..=b'9', s @ b'0'..=b'9', t @ b'0'..=b'9', multiline @ (b' ' | b'-'), rest @ ..] =>
-                {
-                    self.code = [*f, *s, *t];
-                    self.cursor += output.append_bytes(rest, self.cursor);
-
-                    if *multiline == b'-' {
-                        self.cursor += output.append_bytes(NEWLINE, self.cursor);
-                        self.state = ParsedResponseState::Partial;
-                    } else {
-                        self.state = ParsedResponseState::Complete;
-                    }
+            State::Code => {
+                let valid = match self.prefix_len {
+                    0..=2 => byte.is_ascii_digit(),
+                    _ => matches!(byte, b' ' | b'-'),
+                };
+
+                if !valid {
+                    return Err(Error::IncompleteResponse);
                 }
-                _ => return Err(Error::IncompleteResponse),
-            },
-            ParsedResponseState::Partial => {
-                // TODO: Should we detect incorrect codes here?
-                // 220-
-                // 210 # expected 220
-                match input {
-                    [f @ b'0'..=b'9', s @ b'0'..=b'9', t @ b'0'..=b'9', b' ', rest @ ..]
-                        if [*f, *s, *t] == self.code =>
-                    {
-                        self.cursor += output.append_bytes(rest, self.cursor);
-                        self.state = ParsedResponseState::Complete;
-                    }
-                    [f @ b'0'..=b'9', s @ b'0'..=b'9', t @ b'0'..=b'9', b'-', rest @ ..]
-                        if [*f, *s, *t] == self.code =>
-                    {
-                        self.cursor += output.append_bytes(rest, self.cursor);
-                        self.cursor += output.append_bytes(NEWLINE, self.cursor);
-                    }
-                    _ => {
-                        self.cursor += output.append_bytes(input, self.cursor);
-                        self.cursor += output.append_bytes(NEWLINE, self.cursor);
-                    }
+
+                self.prefix[self.prefix_len] = byte;
+                self.prefix_len += 1;
+
+                if self.prefix_len == self.prefix.len() {
+                    self.code.copy_from_slice(&self.prefix[..3]);
+                    self.prefix_len = 0;
+                    self.state = State::Text { last: byte == b' ' };
                 }
             }
-            ParsedResponseState::Complete => {}
+            State::Text { last: true } if byte == b'\n' => self.state = State::Complete,
+            State::Text { last: false } if byte == b'\n' => {
+                if !self.write(&[byte], output)? {
+                    return Ok(false);
+                }
+
+                self.state = State::Prefix;
+            }
+            State::Text { .. } => return self.write(&[byte], output),
+            // Lines too short to start with the code
+            State::Prefix if byte == b'\n' => {
+                let mut line = self.prefix;
+                line[self.prefix_len] = byte;
+
+                if !self.write(&line[..self.prefix_len + 1], output)? {
+                    return Ok(false);
+                }
+
+                self.prefix_len = 0;
+            }
+            State::Prefix if self.prefix_len < self.prefix.len() - 1 => {
+                self.prefix[self.prefix_len] = byte;
+                self.prefix_len += 1;
+            }
+            State::Prefix => {
+                let mut line = self.prefix;
+                line[self.prefix_len] = byte;
+
+                // Only the code followed by a space ends the reply, any other
+                // line is text even if it starts with digits. Servers have to
+                // pad text that looks like a different terminator, so that
+                // is taken to be a broken reply rather than text
+                let state = match line {
+                    [f, s, t, separator @ (b' ' | b'-')] if [f, s, t] == self.code => State::Text {
+                        last: separator == b' ',
+                    },
+                    [f @ b'0'..=b'9', s @ b'0'..=b'9', t @ b'0'..=b'9', b' '] => {
+                        return Err(Error::MismatchedReplyCode {
+                            expected: self.code,
+                            found: [f, s, t],
+                        })
+                    }
+                    _ if !self.write(&line, output)? => return Ok(false),
+                    _ => State::Text { last: false },
+                };
+
+                self.prefix_len = 0;
+                self.state = state;
+            }
+            State::Complete => {}
         }
 
-        Ok(self.state)
+        Ok(true)
+    }
+
+    fn write(&mut self, bytes: &[u8], output: &mut [u8]) -> Result<bool> {
+        let end = self.cursor + bytes.len();
+        let newlines = self.newlines + bytes.iter().filter(|byte| **byte == b'\n').count();
+
+        if end > self.limits.max_size || newlines >= self.limits.max_lines {
+            return Err(Error::ReplyTooLarge);
+        }
+
+        match output.get_mut(self.cursor..end) {
+            Some(output) => {
+                output.copy_from_slice(bytes);
+                self.cursor = end;
+                self.newlines = newlines;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
     }
 }
 
@@ -103,36 +244,29 @@ impl ResponseExt for Response {
 
         Ok(addr)
     }
+
+    fn parse_extended_passive_mode(&mut self, input: &[u8]) -> Result<u16> {
+        parse_extended_passive_mode(input)
+    }
+
+    fn parse_size(&mut self, input: &[u8]) -> Result<u64> {
+        parse_size(input)
+    }
 }
 
 pub trait ResponseExt {
     fn parse_passive_mode(&mut self, input: &[u8]) -> Result<Addr>;
-}
 
-trait AppendBytes {
-    fn append_bytes(&mut self, buf: &[u8], cursor: usize) -> usize;
-}
-
-impl AppendBytes for &mut [u8] {
-    fn append_bytes(&mut self, buf: &[u8], cursor: usize) -> usize {
-        // Trim CRLF
-        let b = match buf {
-            [rest @ .., b'\r', b'\n'] => rest,
-            _ => buf,
-        };
+    fn parse_extended_passive_mode(&mut self, input: &[u8]) -> Result<u16>;
 
-        self[cursor..cursor + b.len()].copy_from_slice(b);
-        b.len()
-    }
+    fn parse_size(&mut self, input: &[u8]) -> Result<u64>;
 }
 
 #[cfg(test)]
 mod tests {
-    use core::io::{BufRead, BufReader};
-
     use crate::{
-        response::{ParsedResponseState, Response, ResponseExt},
-        Code, Result,
+        response::{Addr, Limits, Response, ResponseExt, Status},
+        Code, Error, Result,
     };
 
     #[test]
@@ -197,11 +331,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parses_digit_lines_successfully() {
+        let mut buf: [u8; 4096] = [0; 4096];
+
+        let resp =
+            parse_response(include_bytes!("../testdata/digit_lines_input"), &mut buf).unwrap();
+
+        assert_eq!(resp.code().unwrap(), Code::SYSTEM_STATUS);
+        assert_eq!(
+            resp.message(&buf),
+            stripped(include_bytes!("../testdata/digit_lines_output"))
+        );
+    }
+
+    #[test]
+    fn test_parses_mixed_line_endings_successfully() {
+        let mut buf: [u8; 4096] = [0; 4096];
+
+        let resp = parse_response(
+            include_bytes!("../testdata/mixed_line_endings_input"),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(resp.code().unwrap(), Code::LOGGED_IN);
+        assert_eq!(
+            resp.message(&buf),
+            stripped(include_bytes!("../testdata/mixed_line_endings_output"))
+        );
+    }
+
+    #[test]
+    fn test_rejects_mismatched_code() {
+        let mut buf: [u8; 4096] = [0; 4096];
+
+        assert!(matches!(
+            parse_response(
+                include_bytes!("../testdata/mismatched_code_input"),
+                &mut buf
+            ),
+            Err(Error::MismatchedReplyCode {
+                expected: [b'2', b'2', b'0'],
+                found: [b'2', b'1', b'0'],
+            })
+        ));
+    }
+
     #[test]
     fn test_response_parse_passive_mode() {
         let mut resp = Response::new();
 
-        let addr = ([127, 0, 0, 1], 30001);
+        let addr = Addr::from(([127, 0, 0, 1], 30001));
 
         assert_eq!(
             resp.parse_passive_mode(b"Entering Passive Mode (127,0,0,1,117,49)")
@@ -210,18 +391,177 @@ mod tests {
         );
     }
 
-    fn parse_response(buf: &[u8], response_buf: &mut [u8]) -> Result<Response> {
-        let r = BufReader::new(buf);
+    #[test]
+    fn test_response_parse_extended_passive_mode() {
+        let mut resp = Response::new();
+
+        assert_eq!(
+            resp.parse_extended_passive_mode(b"Entering Extended Passive Mode (|||30001|)")
+                .unwrap(),
+            30001
+        );
+    }
 
-        let mut lines = r.lines();
+    #[test]
+    fn test_parses_chunks() {
+        let input = include_bytes!("../testdata/welcome_message_input");
+        let expected = stripped(include_bytes!("../testdata/welcome_message_output"));
 
-        let mut parsed_response = Response::new();
+        for size in 1..input.len() {
+            let mut buf: [u8; 4096] = [0; 4096];
+            let mut resp = Response::new();
+
+            let mut status = Status::Partial(0);
+            let mut last = 0;
+
+            for chunk in input.chunks(size) {
+                status = resp.parse(chunk, &mut buf).unwrap();
+                last = chunk.len();
+            }
+
+            assert_eq!(status, Status::Complete(last), "{}", size);
+            assert_eq!(resp.message(&buf), expected, "{}", size);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parses_pipelined_replies() {
+        let input = b"200 Ok\r\n150-Opening\n 150 data\r\n150 connection\n226 Done\r\n";
+        let mut buf: [u8; 64] = [0; 64];
+
+        let mut replies = Vec::new();
+        let mut input = &input[..];
+
+        while !input.is_empty() {
+            let mut resp = Response::new();
+
+            match resp.parse(input, &mut buf).unwrap() {
+                Status::Complete(n) => input = &input[n..],
+                Status::Partial(n) => panic!("partial reply after {} bytes", n),
+            }
 
-        while let ParsedResponseState::Partial =
-            parsed_response.read_bytes(lines.next().unwrap().unwrap().as_bytes(), response_buf)?
-        {
+            replies.push((resp.code().unwrap(), resp.message(&buf).to_vec()));
         }
 
+        assert_eq!(
+            replies,
+            [
+                (Code::COMMAND_OKAY, b"Ok".to_vec()),
+                (
+                    Code::OPENING_DATA_CONNECTION,
+                    b"Opening\n 150 data\nconnection".to_vec()
+                ),
+                (Code::CLOSING_DATA_CONNECTION, b"Done".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parses_carriage_returns_in_message() {
+        let mut buf: [u8; 64] = [0; 64];
+        let mut resp = Response::new();
+
+        assert_eq!(
+            resp.parse(b"220 a\rb\r", &mut buf).unwrap(),
+            Status::Partial(8)
+        );
+        assert_eq!(resp.parse(b"\r\n", &mut buf).unwrap(), Status::Complete(2));
+        assert_eq!(resp.message(&buf), b"a\rb\r");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parse_into_full_buffer() {
+        let input = include_bytes!("../testdata/feat_command_input");
+        let mut buf = Vec::new();
+        let mut resp = Response::new();
+
+        let mut consumed = 0;
+
+        loop {
+            match resp.parse(&input[consumed..], &mut buf).unwrap() {
+                Status::Complete(n) => {
+                    consumed += n;
+                    break;
+                }
+                Status::Partial(n) => {
+                    consumed += n;
+                    buf.resize(buf.len() + 7, 0);
+                }
+            }
+        }
+
+        assert_eq!(consumed, input.len());
+        assert_eq!(resp.code().unwrap(), Code::SYSTEM_STATUS);
+        assert_eq!(
+            resp.message(&buf),
+            stripped(include_bytes!("../testdata/feat_command_output"))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_first_line() {
+        for input in [&b"hello\r\n"[..], b"22\r\n", b"220\r\n", b"220_Hi\r\n"] {
+            let mut buf: [u8; 64] = [0; 64];
+
+            assert!(
+                matches!(
+                    Response::new().parse(input, &mut buf),
+                    Err(Error::IncompleteResponse)
+                ),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = Limits {
+            max_size: 16,
+            max_lines: 3,
+        };
+        let mut buf: [u8; 64] = [0; 64];
+
+        let mut resp = Response::with_limits(limits);
+        assert_eq!(
+            resp.parse(b"211-a\r\n b\r\n211 c\r\n", &mut buf).unwrap(),
+            Status::Complete(18)
+        );
+        assert_eq!(resp.message(&buf), b"a\n b\nc");
+
+        let mut resp = Response::with_limits(limits);
+        assert_eq!(
+            resp.parse(b"220 0123456789abcdef\r\n", &mut buf).unwrap(),
+            Status::Complete(22)
+        );
+
+        for input in [
+            &b"211-a\r\n b\r\n c\r\n211 d\r\n"[..],
+            b"211-\r\n\r\n\r\n\r\n",
+            b"220 0123456789abcdefg",
+            b"220-01234567\r\n220 89abcdefg\r\n",
+        ] {
+            assert!(
+                matches!(
+                    Response::with_limits(limits).parse(input, &mut buf),
+                    Err(Error::ReplyTooLarge)
+                ),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    fn parse_response(buf: &[u8], response_buf: &mut [u8]) -> Result<Response> {
+        let mut parsed_response = Response::new();
+
+        assert_eq!(
+            parsed_response.parse(buf, response_buf)?,
+            Status::Complete(buf.len())
+        );
+
         Ok(parsed_response)
     }
 
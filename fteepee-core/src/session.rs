@@ -0,0 +1,1162 @@
+//! The protocol side of an FTP client, without any I/O.
+//!
+//! A [`Session`] is given the bytes read from the control connection and
+//! tells its driver what to do next through [`Action`]s, which keeps the
+//! blocking and async clients down to moving bytes around.
+
+use std::{mem, net::SocketAddr, ops::RangeInclusive, string::String, vec::Vec};
+
+use crate::{
+    commands::{
+        check_arg, Auth, Cdup, Command, Cwd, Dele, Eprt, Epsv, Feat, List, Mkd, Mlsd, Pass, Pasv,
+        Pbsz, Port, Prot, Pwd, Rest, Retr, Rmd, Rnfr, Rnto, Size, Stor, Syst, Type, User,
+    },
+    expect_code,
+    parsers::{self, ListFormat},
+    response::{Limits, Response, Status},
+    Code, Config, DataMode, DateTime, DirEntry, Encoding, Error, FileError, Reply, Result,
+    TransferType,
+};
+
+/// What the driver of a [`Session`] has to do next.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Action<'a> {
+    /// Write these bytes to the control connection.
+    Send(&'a [u8]),
+    /// Read from the control connection and pass the bytes to
+    /// [`Session::receive`].
+    Receive,
+    /// Connect the data connection to this address, then call
+    /// [`Session::data_connected`].
+    Connect(SocketAddr),
+    /// Listen for the data connection on one of these ports, any port if
+    /// `None`, on the local address of the control connection. Then call
+    /// [`Session::listening`] with the address listened on.
+    Listen(Option<RangeInclusive<u16>>),
+    /// Accept the data connection being listened for, then call
+    /// [`Session::data_connected`].
+    Accept,
+    /// Transfer the data, close the data connection and call
+    /// [`Session::transfer_complete`].
+    Transfer,
+    /// The operation finished.
+    Done(Output),
+}
+
+/// The result of a finished operation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Output {
+    None,
+    /// Reply to SIZE.
+    Size(u64),
+    /// Reply to PWD.
+    Pathname(String),
+    /// The final reply to logging in.
+    Reply(Reply),
+}
+
+/// Tracks the state of the control connection from the greeting onwards.
+///
+/// Operations such as [`Session::login`] are started on the session, which
+/// is then driven by calling [`Session::poll`] and doing whatever each
+/// [`Action`] asks until it returns [`Action::Done`]. Only one operation can
+/// be in progress at a time, starting another abandons the current one.
+///
+/// A new session expects the server's greeting and then discovers what the
+/// server supports using FEAT and SYST.
+pub struct Session {
+    config: Config,
+    peer: SocketAddr,
+    state: State,
+    /// Bytes received that are not part of the current reply yet.
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pending_output: bool,
+    response: Response,
+    reply: Vec<u8>,
+    welcome: Option<Reply>,
+    /// When the current listing was started, LIST dates are relative to it.
+    listed_at: DateTime,
+}
+
+const REPLY_BUFFER_SIZE: usize = 256;
+
+enum State {
+    Reply(Step),
+    Connect(SocketAddr),
+    Listen(Transfer),
+    Accept,
+    Transfer,
+    Done(Output),
+    /// The operation failed before anything was sent.
+    Failed(Error),
+}
+
+/// The command a reply is expected for, along with anything needed to
+/// carry on once it arrives.
+enum Step {
+    Greeting,
+    Feat,
+    Syst,
+    User {
+        pass: String,
+    },
+    Pass,
+    Auth,
+    Pbsz,
+    Prot,
+    SizeType {
+        path: String,
+    },
+    Size {
+        path: String,
+    },
+    TransferType(Transfer),
+    Epsv(Transfer),
+    Pasv(Transfer),
+    Port(Transfer),
+    Rest(Transfer),
+    TransferStarting {
+        passive: bool,
+    },
+    TransferComplete,
+    TransferFailed,
+    /// CWD and CDUP
+    Cwd {
+        path: String,
+    },
+    /// RMD and DELE
+    FileAction {
+        path: String,
+    },
+    Mkd {
+        path: String,
+    },
+    Pwd,
+    Rnfr {
+        from: String,
+        to: String,
+    },
+    Rnto {
+        to: String,
+    },
+}
+
+struct Transfer {
+    kind: TransferKind,
+    path: String,
+    offset: u64,
+    /// Where to connect to in passive mode.
+    addr: Option<SocketAddr>,
+}
+
+enum TransferKind {
+    List,
+    Retrieve,
+    Store,
+}
+
+impl Session {
+    /// Starts a session on a control connection to `peer`.
+    pub fn new(peer: SocketAddr) -> Self {
+        Self::with_config(peer, Config::default())
+    }
+
+    /// Starts a session with options such as [`Config::data_mode`] already
+    /// set, what the server supports is still discovered.
+    pub fn with_config(peer: SocketAddr, config: Config) -> Self {
+        Self {
+            config,
+            peer,
+            state: State::Reply(Step::Greeting),
+            input: Vec::new(),
+            output: Vec::new(),
+            pending_output: false,
+            response: Response::new(),
+            reply: Vec::new(),
+            welcome: None,
+            listed_at: DateTime::default(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The server's greeting, once it has been received.
+    pub fn welcome(&self) -> Option<&Reply> {
+        self.welcome.as_ref()
+    }
+
+    pub fn set_data_mode(&mut self, mode: DataMode) {
+        self.config.data_mode = mode;
+    }
+
+    pub fn set_reply_limits(&mut self, limits: Limits) {
+        self.config.reply_limits = limits;
+        self.response.set_limits(limits);
+    }
+
+    /// Advances the current operation as far as possible, returning what the
+    /// driver has to do next.
+    ///
+    /// Once an operation fails the session is ready for the next one.
+    pub fn poll(&mut self) -> Result<Action<'_>> {
+        loop {
+            if self.pending_output {
+                self.pending_output = false;
+                return Ok(Action::Send(&self.output));
+            }
+
+            // Failing from here on leaves the operation finished
+            let step = match mem::replace(&mut self.state, State::Done(Output::None)) {
+                State::Reply(step) => step,
+                State::Failed(err) => return Err(err),
+                state => {
+                    self.state = state;
+                    return Ok(self.action());
+                }
+            };
+
+            let reply = match self.read_reply() {
+                Ok(Some(code)) => Reply::from_message(code, self.decode(self.message()).as_bytes()),
+                Ok(None) => {
+                    self.state = State::Reply(step);
+                    return Ok(Action::Receive);
+                }
+                Err(err) => {
+                    self.response = Response::with_limits(self.config.reply_limits);
+                    return Err(err);
+                }
+            };
+
+            let result = self.advance(step, reply);
+
+            self.response = Response::with_limits(self.config.reply_limits);
+
+            result?;
+        }
+    }
+
+    /// Hands the session bytes read from the control connection.
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+    }
+
+    /// Tells the session the data connection asked for by
+    /// [`Action::Connect`] or [`Action::Accept`] is open.
+    pub fn data_connected(&mut self) {
+        self.state = match mem::replace(&mut self.state, State::Done(Output::None)) {
+            // The server confirms the transfer once we are connected
+            State::Connect(_) => State::Reply(Step::TransferStarting { passive: true }),
+            State::Accept => State::Transfer,
+            state => state,
+        };
+    }
+
+    /// Tells the session the driver is listening on `addr` as asked for by
+    /// [`Action::Listen`].
+    pub fn listening(&mut self, addr: SocketAddr) {
+        let transfer = match mem::replace(&mut self.state, State::Done(Output::None)) {
+            State::Listen(transfer) => transfer,
+            state => {
+                self.state = state;
+                return;
+            }
+        };
+
+        let ip = match self.config.data_mode {
+            DataMode::Active {
+                external_ip: Some(ip),
+                ..
+            } => ip,
+            _ => addr.ip(),
+        };
+
+        // PORT can only describe IPv4 addresses
+        match SocketAddr::new(ip, addr.port()) {
+            SocketAddr::V4(addr) => self.send(&Port::new(addr), Step::Port(transfer)),
+            addr @ SocketAddr::V6(_) => self.send(&Eprt::new(addr), Step::Port(transfer)),
+        }
+    }
+
+    /// Tells the session the data connection was closed after the transfer
+    /// asked for by [`Action::Transfer`].
+    pub fn transfer_complete(&mut self) {
+        if matches!(self.state, State::Transfer) {
+            self.state = State::Reply(Step::TransferComplete);
+        }
+    }
+
+    /// Tells the session the transfer asked for by [`Action::Transfer`]
+    /// failed and the data connection was closed. The server's reply to the
+    /// transfer is still read, whatever it is, so it isn't taken for the
+    /// reply to the next command.
+    pub fn transfer_failed(&mut self) {
+        if matches!(self.state, State::Transfer) {
+            self.state = State::Reply(Step::TransferFailed);
+        }
+    }
+
+    pub fn login(&mut self, user: &str, pass: &str) {
+        if !self.check_args(&[user, pass]) {
+            return;
+        }
+
+        self.send(
+            &User::new(user),
+            Step::User {
+                pass: pass.to_owned(),
+            },
+        );
+    }
+
+    /// Asks the server to start a TLS handshake on the control connection,
+    /// the driver performs the handshake once the operation is done.
+    pub fn auth_tls(&mut self) {
+        self.send(&Auth::new("TLS"), Step::Auth);
+    }
+
+    /// Asks the server to protect data connections with TLS.
+    pub fn protect_data(&mut self) {
+        self.send(&Pbsz::new(0), Step::Pbsz);
+    }
+
+    /// Lists `path`, using MLSD if the server supports it. Each line read
+    /// from the data connection can be parsed with [`Session::parse_entry`].
+    pub fn list(&mut self, path: &str) {
+        if !self.check_args(&[path]) {
+            return;
+        }
+
+        self.listed_at = DateTime::now();
+        self.open_data(Transfer::new(TransferKind::List, path, 0));
+    }
+
+    /// Downloads `path`, restarting `offset` bytes in if it is not zero.
+    pub fn retrieve(&mut self, path: &str, offset: u64) {
+        if !self.check_args(&[path]) {
+            return;
+        }
+
+        let transfer = Transfer::new(TransferKind::Retrieve, path, offset);
+
+        self.send(&self.transfer_type(), Step::TransferType(transfer));
+    }
+
+    /// Uploads to `path`, restarting `offset` bytes in if it is not zero.
+    pub fn store(&mut self, path: &str, offset: u64) {
+        if !self.check_args(&[path]) {
+            return;
+        }
+
+        let transfer = Transfer::new(TransferKind::Store, path, offset);
+
+        self.send(&self.transfer_type(), Step::TransferType(transfer));
+    }
+
+    /// Finishes with [`Output::Size`].
+    pub fn size(&mut self, path: &str) {
+        if !self.check_args(&[path]) {
+            return;
+        }
+
+        // The size of a file depends on the transfer type
+        self.send(
+            &Type::Image,
+            Step::SizeType {
+                path: path.to_owned(),
+            },
+        );
+    }
+
+    pub fn cwd(&mut self, path: &str) {
+        self.send(
+            &Cwd::new(path),
+            Step::Cwd {
+                path: path.to_owned(),
+            },
+        );
+    }
+
+    pub fn cdup(&mut self) {
+        self.send(&Cdup, Step::Cwd { path: "..".into() });
+    }
+
+    /// Finishes with [`Output::Pathname`].
+    pub fn pwd(&mut self) {
+        self.send(&Pwd, Step::Pwd);
+    }
+
+    pub fn mkdir(&mut self, path: &str) {
+        self.send(
+            &Mkd::new(path),
+            Step::Mkd {
+                path: path.to_owned(),
+            },
+        );
+    }
+
+    pub fn rmdir(&mut self, path: &str) {
+        self.send(
+            &Rmd::new(path),
+            Step::FileAction {
+                path: path.to_owned(),
+            },
+        );
+    }
+
+    pub fn remove_file(&mut self, path: &str) {
+        self.send(
+            &Dele::new(path),
+            Step::FileAction {
+                path: path.to_owned(),
+            },
+        );
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if !self.check_args(&[from, to]) {
+            return;
+        }
+
+        self.send(
+            &Rnfr::new(from),
+            Step::Rnfr {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            },
+        );
+    }
+
+    /// Parses a line of the current listing, see [`Session::list`].
+    ///
+    /// Returns `None` for lines that do not describe an entry.
+    pub fn parse_entry(&self, line: &[u8]) -> Result<Option<DirEntry>> {
+        let line = self.decode(line);
+
+        if self.config.mlst_supported {
+            parsers::parse_mlsd_entry(&line)
+        } else {
+            parsers::parse_list_entry(&line, self.config.list_format, self.listed_at)
+        }
+    }
+
+    fn action(&self) -> Action<'_> {
+        match &self.state {
+            State::Reply(_) => Action::Receive,
+            State::Connect(addr) => Action::Connect(*addr),
+            State::Listen(_) => Action::Listen(match &self.config.data_mode {
+                DataMode::Active { ports, .. } => ports.clone(),
+                DataMode::Passive => None,
+            }),
+            State::Accept => Action::Accept,
+            State::Transfer => Action::Transfer,
+            State::Done(output) => Action::Done(output.clone()),
+            State::Failed(_) => unreachable!("failures are returned by poll"),
+        }
+    }
+
+    /// Handles the reply to the command sent at `step`.
+    fn advance(&mut self, step: Step, reply: Reply) -> Result<()> {
+        let code = reply.code;
+
+        match step {
+            Step::Greeting => {
+                expect_code!(reply, Code::READY);
+                self.welcome = Some(reply);
+
+                if self.config.probe_features {
+                    self.send(&Feat, Step::Feat);
+                } else {
+                    self.send(&Syst, Step::Syst);
+                }
+            }
+            Step::Feat => {
+                expect_code!(
+                    reply,
+                    Code::SYSTEM_STATUS | Code::UNRECOGNIZED_COMMAND | Code::NOT_IMPLEMENTED
+                );
+
+                if code == Code::SYSTEM_STATUS {
+                    let features = parsers::parse_features(self.message())?;
+                    let (mlst, epsv) =
+                        (features.contains_key("MLST"), features.contains_key("EPSV"));
+
+                    self.config.mlst_supported = mlst;
+                    self.config.epsv_supported = epsv;
+                }
+
+                self.send(&Syst, Step::Syst);
+            }
+            Step::Syst => {
+                // Some servers refuse SYST before login, it is only a hint so
+                // carry on
+                if code == Code::SYSTEM_TYPE {
+                    let system = String::from_utf8_lossy(self.message());
+                    self.config.list_format = ListFormat::from_system(&system);
+                }
+
+                self.finish(Output::None);
+            }
+            Step::User { pass } => {
+                expect_code!(reply, Code::LOGGED_IN | Code::PASSWORD_REQUIRED);
+
+                if code == Code::LOGGED_IN {
+                    self.finish(Output::Reply(reply));
+                } else {
+                    self.send(&Pass::new(&pass), Step::Pass);
+                }
+            }
+            Step::Pass => {
+                expect_code!(reply, Code::LOGGED_IN);
+                self.finish(Output::Reply(reply));
+            }
+            Step::Auth => {
+                expect_code!(reply, Code::SECURITY_DATA_EXCHANGE_COMPLETE);
+
+                // Anything received before the handshake could have been
+                // injected
+                if !self.input.is_empty() {
+                    return Err(Error::DataBeforeHandshake);
+                }
+
+                self.finish(Output::None);
+            }
+            Step::Pbsz => {
+                expect_code!(reply, Code::COMMAND_OKAY);
+                self.send(&Prot::Private, Step::Prot);
+            }
+            Step::Prot => {
+                expect_code!(reply, Code::COMMAND_OKAY);
+                self.finish(Output::None);
+            }
+            Step::SizeType { path } => {
+                expect_code!(reply, Code::COMMAND_OKAY);
+                self.encode(&Size::new(&path));
+                self.expect_reply(Step::Size { path });
+            }
+            Step::Size { path } => {
+                self.check_file_action(code, &path)?;
+                expect_code!(reply, Code::FILE_STATUS);
+
+                let size = parsers::parse_size(self.message())?;
+                self.finish(Output::Size(size));
+            }
+            Step::TransferType(transfer) => {
+                expect_code!(reply, Code::COMMAND_OKAY);
+                self.open_data(transfer);
+            }
+            Step::Epsv(transfer) => match code {
+                Code::ENTERING_EXTENDED_PASSIVE_MODE => {
+                    let port = parsers::parse_extended_passive_mode(self.message())?;
+                    let addr = SocketAddr::new(self.peer.ip(), port);
+
+                    self.request_transfer(transfer, Some(addr));
+                }
+                Code::UNRECOGNIZED_COMMAND | Code::NOT_IMPLEMENTED if self.peer.is_ipv4() => {
+                    self.config.epsv_supported = false;
+                    self.send(&Pasv, Step::Pasv(transfer));
+                }
+                _ => return Err(Error::UnexpectedCode(reply)),
+            },
+            Step::Pasv(transfer) => {
+                expect_code!(reply, Code::ENTERING_PASSIVE_MODE);
+
+                // Servers behind NAT often give their private address, and
+                // trusting any other host would let the server bounce our
+                // connection elsewhere, so only the port is used
+                let addr = parsers::parse_passive_mode(self.message())?;
+                let addr = SocketAddr::new(self.peer.ip(), addr.port());
+
+                self.request_transfer(transfer, Some(addr));
+            }
+            Step::Port(transfer) => {
+                expect_code!(reply, Code::COMMAND_OKAY);
+                self.request_transfer(transfer, None);
+            }
+            Step::Rest(transfer) => {
+                expect_code!(
+                    reply,
+                    Code::REQUESTED_FILE_ACTION_PENDING_FURTHER_INFORMATION
+                );
+                self.send_transfer(transfer);
+            }
+            Step::TransferStarting { passive } => {
+                expect_code!(
+                    reply,
+                    Code::DATA_CONNECTION_ALREADY_OPEN | Code::OPENING_DATA_CONNECTION
+                );
+
+                // The server only connects once it has accepted the command
+                self.state = if passive {
+                    State::Transfer
+                } else {
+                    State::Accept
+                };
+            }
+            Step::TransferComplete => {
+                expect_code!(
+                    reply,
+                    Code::CLOSING_DATA_CONNECTION | Code::REQUESTED_FILE_ACTION_OKAY
+                );
+                self.finish(Output::None);
+            }
+            Step::TransferFailed => self.finish(Output::None),
+            Step::Cwd { path } => {
+                self.check_file_action(code, &path)?;
+                expect_code!(reply, Code::REQUESTED_FILE_ACTION_OKAY | Code::COMMAND_OKAY);
+                self.finish(Output::None);
+            }
+            Step::FileAction { path } => {
+                self.check_file_action(code, &path)?;
+                expect_code!(reply, Code::REQUESTED_FILE_ACTION_OKAY);
+                self.finish(Output::None);
+            }
+            Step::Mkd { path } => {
+                self.check_file_action(code, &path)?;
+                expect_code!(reply, Code::CREATED);
+                self.finish(Output::None);
+            }
+            Step::Pwd => {
+                expect_code!(reply, Code::CREATED);
+
+                let pathname = parsers::parse_pathname(self.decode(self.message()).as_bytes())?;
+                self.finish(Output::Pathname(pathname));
+            }
+            Step::Rnfr { from, to } => {
+                self.check_file_action(code, &from)?;
+                expect_code!(
+                    reply,
+                    Code::REQUESTED_FILE_ACTION_PENDING_FURTHER_INFORMATION
+                );
+                self.encode(&Rnto::new(&to));
+                self.expect_reply(Step::Rnto { to });
+            }
+            Step::Rnto { to } => {
+                self.check_file_action(code, &to)?;
+                expect_code!(reply, Code::REQUESTED_FILE_ACTION_OKAY);
+                self.finish(Output::None);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses buffered input into the current reply, returning its code once
+    /// it is complete.
+    fn read_reply(&mut self) -> Result<Option<Code>> {
+        loop {
+            let status = self.response.parse(&self.input, &mut self.reply);
+
+            match status? {
+                Status::Complete(n) => {
+                    self.input.drain(..n);
+                    return self.response.code().map(Some);
+                }
+                Status::Partial(n) if n == self.input.len() => {
+                    self.input.clear();
+                    return Ok(None);
+                }
+                // The buffer is kept between replies, it only grows up to
+                // the size limit as the parser fails past it
+                Status::Partial(n) => {
+                    self.input.drain(..n);
+
+                    let len = (self.reply.len() * 2)
+                        .max(REPLY_BUFFER_SIZE)
+                        .min(self.config.reply_limits.max_size);
+                    self.reply.resize(len, 0);
+                }
+            }
+        }
+    }
+
+    fn message(&self) -> &[u8] {
+        self.response.message(&self.reply)
+    }
+
+    fn decode<'a>(&self, bytes: &'a [u8]) -> std::borrow::Cow<'a, str> {
+        self.config.encoding.decode(bytes)
+    }
+
+    fn transfer_type(&self) -> Type {
+        match self.config.transfer_type {
+            TransferType::Binary => Type::Image,
+            TransferType::Ascii => Type::ASCII,
+        }
+    }
+
+    /// Turns replies that refuse to act on `path` into errors naming it.
+    fn check_file_action(&self, code: Code, path: &str) -> Result<()> {
+        let kind = match code {
+            Code::FILE_UNAVAILABLE => FileError::Unavailable,
+            Code::FILE_NAME_NOT_ALLOWED => FileError::NameNotAllowed,
+            _ => return Ok(()),
+        };
+
+        let message = self.decode(self.message());
+
+        Err(Error::File {
+            kind,
+            path: path.to_owned(),
+            message: message.trim().to_owned(),
+        })
+    }
+
+    fn send<C: Command>(&mut self, cmd: &C, step: Step) {
+        if let Err(err) = cmd.check() {
+            return self.fail(err);
+        }
+
+        self.encode(cmd);
+        self.expect_reply(step);
+    }
+
+    /// Fails the operation before anything is sent if any of `args`, which
+    /// are sent by later steps, could inject a command. Returns whether they
+    /// are fine.
+    fn check_args(&mut self, args: &[&str]) -> bool {
+        match args.iter().try_for_each(|arg| check_arg(arg.as_bytes())) {
+            Ok(()) => true,
+            Err(err) => {
+                self.fail(err);
+                false
+            }
+        }
+    }
+
+    fn fail(&mut self, err: Error) {
+        self.pending_output = false;
+        self.state = State::Failed(err);
+    }
+
+    fn encode<C: Command>(&mut self, cmd: &C) {
+        self.output.clear();
+        self.output.resize(cmd.size(), 0);
+
+        cmd.encode(&mut self.output);
+
+        // Commands are built from UTF-8 strings
+        if self.config.encoding != Encoding::Utf8 {
+            let text = String::from_utf8_lossy(&self.output);
+            let encoded = self.config.encoding.encode(&text).into_owned();
+
+            self.output = encoded;
+        }
+
+        self.pending_output = true;
+    }
+
+    fn expect_reply(&mut self, step: Step) {
+        self.state = State::Reply(step);
+    }
+
+    fn finish(&mut self, output: Output) {
+        self.state = State::Done(output);
+    }
+
+    fn open_data(&mut self, transfer: Transfer) {
+        match self.config.data_mode {
+            // PASV can only describe IPv4 addresses
+            DataMode::Passive if self.config.epsv_supported || self.peer.is_ipv6() => {
+                self.send(&Epsv, Step::Epsv(transfer))
+            }
+            DataMode::Passive => self.send(&Pasv, Step::Pasv(transfer)),
+            DataMode::Active { .. } => self.state = State::Listen(transfer),
+        }
+    }
+
+    /// REST has to be sent right before the transfer command, after PASV or
+    /// PORT.
+    fn request_transfer(&mut self, mut transfer: Transfer, addr: Option<SocketAddr>) {
+        transfer.addr = addr;
+
+        if transfer.offset > 0 {
+            self.send(&Rest::new(transfer.offset), Step::Rest(transfer));
+        } else {
+            self.send_transfer(transfer);
+        }
+    }
+
+    fn send_transfer(&mut self, transfer: Transfer) {
+        let step = Step::TransferStarting {
+            passive: transfer.addr.is_some(),
+        };
+
+        match transfer.kind {
+            TransferKind::List if self.config.mlst_supported => {
+                self.send(&Mlsd::new(&transfer.path), step)
+            }
+            TransferKind::List => self.send(&List::new(&transfer.path), step),
+            TransferKind::Retrieve => self.send(&Retr::new(&transfer.path), step),
+            TransferKind::Store => self.send(&Stor::new(&transfer.path), step),
+        }
+
+        // Passive data connections are made once the command is sent
+        if let Some(addr) = transfer.addr {
+            self.state = State::Connect(addr);
+        }
+    }
+}
+
+impl Transfer {
+    fn new(kind: TransferKind, path: &str, offset: u64) -> Self {
+        Self {
+            kind,
+            path: path.to_owned(),
+            offset,
+            addr: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+
+    fn sent(session: &mut Session) -> String {
+        match session.poll().unwrap() {
+            Action::Send(bytes) => String::from_utf8(bytes.to_vec()).unwrap(),
+            action => panic!("expected a command, got {:?}", action),
+        }
+    }
+
+    fn established(peer: SocketAddr, features: &str) -> Session {
+        let mut session = Session::new(peer);
+
+        assert_eq!(session.poll().unwrap(), Action::Receive);
+        // Replies can be split anywhere
+        session.receive(b"220-Welcome\r\n22");
+        assert_eq!(session.poll().unwrap(), Action::Receive);
+        session.receive(b"0 Ready\r\n");
+
+        assert_eq!(sent(&mut session), "FEAT\r\n");
+        session.receive(features.as_bytes());
+
+        assert_eq!(sent(&mut session), "SYST\r\n");
+        session.receive(b"215 UNIX Type: L8\r\n");
+
+        assert_eq!(session.poll().unwrap(), Action::Done(Output::None));
+
+        session
+    }
+
+    fn localhost() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 21))
+    }
+
+    #[test]
+    fn test_establish() {
+        let session = established(
+            localhost(),
+            "211-Features:\r\n MLST type*;size*;\r\n EPSV\r\n211 End\r\n",
+        );
+
+        assert!(session.config().mlst_supported);
+        assert!(session.config().epsv_supported);
+        assert_eq!(session.config().list_format, Some(ListFormat::Unix));
+        assert_eq!(
+            session.welcome(),
+            Some(&Reply::new(
+                Code::READY,
+                vec!["Welcome".into(), "Ready".into()]
+            ))
+        );
+
+        let session = established(localhost(), "502 Not implemented\r\n");
+
+        assert!(!session.config().mlst_supported);
+        assert!(!session.config().epsv_supported);
+    }
+
+    #[test]
+    fn test_login() {
+        let mut session = established(localhost(), "502 Not implemented\r\n");
+
+        session.login("user", "secret");
+        assert_eq!(sent(&mut session), "USER user\r\n");
+        session.receive(b"331 Password required\r\n");
+        assert_eq!(sent(&mut session), "PASS secret\r\n");
+        session.receive(b"230 Logged in\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Reply(Reply::new(
+                Code::LOGGED_IN,
+                vec!["Logged in".into()]
+            )))
+        );
+
+        // No password needed
+        session.login("anonymous", "");
+        assert_eq!(sent(&mut session), "USER anonymous\r\n");
+        session.receive(b"230 Logged in\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Reply(Reply::new(
+                Code::LOGGED_IN,
+                vec!["Logged in".into()]
+            )))
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config {
+            transfer_type: TransferType::Ascii,
+            encoding: Encoding::Latin1,
+            probe_features: false,
+            ..Config::default()
+        };
+        let mut session = Session::with_config(localhost(), config);
+
+        session.receive(b"220 Ready\r\n");
+        assert_eq!(sent(&mut session), "SYST\r\n");
+        session.receive(b"502 Not implemented\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Done(Output::None));
+
+        session.retrieve("caf\u{e9}", 0);
+        assert_eq!(sent(&mut session), "TYPE A\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(sent(&mut session), "PASV\r\n");
+        session.receive(b"227 Entering Passive Mode (127,0,0,1,4,1)\r\n");
+
+        match session.poll().unwrap() {
+            Action::Send(bytes) => assert_eq!(bytes, b"RETR caf\xe9\r\n"),
+            action => panic!("expected a command, got {:?}", action),
+        }
+
+        session.pwd();
+        assert_eq!(sent(&mut session), "PWD\r\n");
+        session.receive(b"257 \"/caf\xe9\"\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Pathname("/caf\u{e9}".into()))
+        );
+    }
+
+    #[test]
+    fn test_passive_uses_peer_ip() {
+        let peer = SocketAddr::from(([192, 0, 2, 1], 21));
+        let mut session = established(peer, "211-Features:\r\n211 End\r\n");
+
+        session.retrieve("file.txt", 0);
+        assert_eq!(sent(&mut session), "TYPE I\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(sent(&mut session), "PASV\r\n");
+        session.receive(b"227 Entering Passive Mode (10,0,0,1,4,1)\r\n");
+        assert_eq!(sent(&mut session), "RETR file.txt\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Connect(SocketAddr::from(([192, 0, 2, 1], 1025)))
+        );
+    }
+
+    #[test]
+    fn test_passive_retrieve() {
+        let mut session = established(localhost(), "211-Features:\r\n EPSV\r\n211 End\r\n");
+
+        session.retrieve("file.txt", 10);
+        assert_eq!(sent(&mut session), "TYPE I\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(sent(&mut session), "EPSV\r\n");
+        session.receive(b"502 Not implemented\r\n");
+        assert_eq!(sent(&mut session), "PASV\r\n");
+        session.receive(b"227 Entering Passive Mode (127,0,0,1,4,1)\r\n");
+        assert_eq!(sent(&mut session), "REST 10\r\n");
+        session.receive(b"350 Restarting\r\n");
+        assert_eq!(sent(&mut session), "RETR file.txt\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Connect(SocketAddr::from(([127, 0, 0, 1], 1025)))
+        );
+
+        session.data_connected();
+        assert_eq!(session.poll().unwrap(), Action::Receive);
+        // The transfer can finish before we get to it
+        session.receive(b"150 Opening\r\n226 Done\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Transfer);
+
+        session.transfer_complete();
+        assert_eq!(session.poll().unwrap(), Action::Done(Output::None));
+        assert!(!session.config().epsv_supported);
+    }
+
+    #[test]
+    fn test_failed_transfer() {
+        let mut session = established(localhost(), "211-Features:\r\n211 End\r\n");
+
+        session.retrieve("file.txt", 0);
+        assert_eq!(sent(&mut session), "TYPE I\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(sent(&mut session), "PASV\r\n");
+        session.receive(b"227 Entering Passive Mode (127,0,0,1,4,1)\r\n");
+        assert_eq!(sent(&mut session), "RETR file.txt\r\n");
+        assert!(matches!(session.poll().unwrap(), Action::Connect(_)));
+
+        session.data_connected();
+        session.receive(b"150 Opening\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Transfer);
+
+        // The data connection broke, the server notices once it is closed
+        session.transfer_failed();
+        assert_eq!(session.poll().unwrap(), Action::Receive);
+        session.receive(b"426 Connection closed; transfer aborted\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Done(Output::None));
+
+        session.pwd();
+        assert_eq!(sent(&mut session), "PWD\r\n");
+        session.receive(b"257 \"/\"\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Pathname("/".into()))
+        );
+    }
+
+    #[test]
+    fn test_injected_command() {
+        let mut session = established(localhost(), "211-Features:\r\n211 End\r\n");
+
+        // Nothing is sent, not even the commands before the bad argument's
+        session.login("user", "pass\r\nDELE a.txt");
+        assert!(matches!(session.poll(), Err(Error::InvalidArgument)));
+        session.retrieve("a.txt\r\nDELE b.txt", 0);
+        assert!(matches!(session.poll(), Err(Error::InvalidArgument)));
+        session.rename("a.txt", "b.txt\nDELE c.txt");
+        assert!(matches!(session.poll(), Err(Error::InvalidArgument)));
+        session.cwd("/pub\0");
+        assert!(matches!(session.poll(), Err(Error::InvalidArgument)));
+
+        session.pwd();
+        assert_eq!(sent(&mut session), "PWD\r\n");
+        session.receive(b"257 \"/\"\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Pathname("/".into()))
+        );
+    }
+
+    #[test]
+    fn test_active_store() {
+        let mut session = established(localhost(), "502 Not implemented\r\n");
+
+        session.set_data_mode(DataMode::Active {
+            external_ip: Some(IpAddr::from([192, 0, 2, 1])),
+            ports: Some(5000..=5010),
+        });
+
+        session.store("upload.bin", 0);
+        assert_eq!(sent(&mut session), "TYPE I\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Listen(Some(5000..=5010)));
+
+        session.listening(SocketAddr::from(([10, 0, 0, 1], 5000)));
+        assert_eq!(sent(&mut session), "PORT 192,0,2,1,19,136\r\n");
+        session.receive(b"200 Ok\r\n");
+        assert_eq!(sent(&mut session), "STOR upload.bin\r\n");
+        session.receive(b"150 Opening\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Accept);
+
+        session.data_connected();
+        assert_eq!(session.poll().unwrap(), Action::Transfer);
+
+        session.transfer_complete();
+        session.receive(b"226 Done\r\n");
+        assert_eq!(session.poll().unwrap(), Action::Done(Output::None));
+    }
+
+    #[test]
+    fn test_extended_passive_list() {
+        let peer = SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 21));
+        let mut session = established(peer, "211-Features:\r\n MLST type*;\r\n211 End\r\n");
+
+        session.list("/");
+        assert_eq!(sent(&mut session), "EPSV\r\n");
+        session.receive(b"229 Entering Extended Passive Mode (|||6446|)\r\n");
+        assert_eq!(sent(&mut session), "MLSD /\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Connect(SocketAddr::new(peer.ip(), 6446))
+        );
+
+        let entry = session.parse_entry(b"type=dir; pub").unwrap().unwrap();
+        assert_eq!(entry.name, "pub");
+    }
+
+    #[test]
+    fn test_file_action_errors() {
+        let mut session = established(localhost(), "502 Not implemented\r\n");
+
+        session.rename("from", "to");
+        assert_eq!(sent(&mut session), "RNFR from\r\n");
+        session.receive(b"550 No such file\r\n");
+
+        match session.poll() {
+            Err(Error::File {
+                kind: FileError::Unavailable,
+                path,
+                message,
+            }) => {
+                assert_eq!(path, "from");
+                assert_eq!(message, "No such file");
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        session.mkdir("dir");
+        assert_eq!(sent(&mut session), "MKD dir\r\n");
+        session.receive(b"452-Disk full\r\n452 Try again later\r\n");
+
+        match session.poll() {
+            Err(err @ Error::UnexpectedCode(_)) => assert_eq!(
+                err.to_string(),
+                "unexpected reply 452 Disk full\nTry again later"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // The session is usable after a failure
+        session.pwd();
+        assert_eq!(sent(&mut session), "PWD\r\n");
+        session.receive(b"257 \"/a \"\"b\"\"\" is current directory\r\n");
+        assert_eq!(
+            session.poll().unwrap(),
+            Action::Done(Output::Pathname("/a \"b\"".into()))
+        );
+    }
+
+    #[test]
+    fn test_reply_limits() {
+        let mut session = established(localhost(), "502 Not implemented\r\n");
+
+        session.set_reply_limits(Limits {
+            max_size: 32,
+            max_lines: 4,
+        });
+
+        session.pwd();
+        assert_eq!(sent(&mut session), "PWD\r\n");
+        session.receive(b"257-\"/\"\r\n");
+
+        // The reply never ends
+        for _ in 0..3 {
+            assert_eq!(session.poll().unwrap(), Action::Receive);
+            session.receive(b"257-padding\r\n");
+        }
+
+        assert!(matches!(session.poll(), Err(Error::ReplyTooLarge)));
+    }
+
+    #[test]
+    fn test_data_before_handshake() {
+        let mut session = established(localhost(), "502 Not implemented\r\n");
+
+        session.auth_tls();
+        assert_eq!(sent(&mut session), "AUTH TLS\r\n");
+        session.receive(b"234 Proceed\r\n230 Injected\r\n");
+
+        assert!(matches!(session.poll(), Err(Error::DataBeforeHandshake)));
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    marker::PhantomData,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use fteepee_core::{
+    response::Limits, session::Session, Config, Connected, DataMode, Encoding, TimeoutPhase,
+    TransferType,
+};
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use rustls::ClientConfig;
+
+use crate::{
+    stream::Stream, timeout, Client, Connector, Proxy, Result, TcpConnector, Timeouts, ToTarget,
+    Transport, DATA_BUFFER_SIZE, READ_BUFFER_SIZE,
+};
+#[cfg(feature = "tls")]
+use crate::{tls::TlsConfig, Error};
+
+/// Sets up a [`Client`] before connecting.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use fteepee_sync::Client;
+///
+/// # fn main() -> fteepee_sync::Result<()> {
+/// let mut client = Client::builder()
+///     .reply_timeout(Duration::from_secs(30))
+///     .probe_features(false)
+///     .connect("ftp.example.com:21")?;
+///
+/// client.login("anonymous", "")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientBuilder {
+    config: Config,
+    timeouts: Timeouts,
+    local_addr: Option<IpAddr>,
+    proxy: Option<Proxy>,
+    read_buffer_size: usize,
+    data_buffer_size: usize,
+    redact_logs: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<Tls>,
+}
+
+#[cfg(feature = "tls")]
+struct Tls {
+    config: Arc<ClientConfig>,
+    domain: String,
+    implicit: bool,
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            timeouts: Timeouts::default(),
+            local_addr: None,
+            proxy: None,
+            read_buffer_size: READ_BUFFER_SIZE,
+            data_buffer_size: DATA_BUFFER_SIZE,
+            redact_logs: true,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// See [`Timeouts::connect`].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// See [`Timeouts::reply`].
+    pub fn reply_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.reply = Some(timeout);
+        self
+    }
+
+    /// See [`Timeouts::data_idle`].
+    pub fn data_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.data_idle = Some(timeout);
+        self
+    }
+
+    /// Defaults to passive mode.
+    pub fn data_mode(mut self, mode: DataMode) -> Self {
+        self.config.data_mode = mode;
+        self
+    }
+
+    /// Defaults to binary.
+    pub fn transfer_type(mut self, transfer_type: TransferType) -> Self {
+        self.config.transfer_type = transfer_type;
+        self
+    }
+
+    /// Defaults to UTF-8.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.config.encoding = encoding;
+        self
+    }
+
+    pub fn reply_limits(mut self, limits: Limits) -> Self {
+        self.config.reply_limits = limits;
+        self
+    }
+
+    /// Whether to send FEAT once connected, on by default. Without it MLSD
+    /// and EPSV are not used.
+    pub fn probe_features(mut self, probe: bool) -> Self {
+        self.config.probe_features = probe;
+        self
+    }
+
+    /// How many bytes are read from the control connection at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "read buffer size must not be zero");
+        self.read_buffer_size = size;
+        self
+    }
+
+    /// How many bytes of a transfer are buffered, defaults to 8 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn data_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "data buffer size must not be zero");
+        self.data_buffer_size = size;
+        self
+    }
+
+    /// Makes the control connection and passive data connections from `ip`,
+    /// only used by [`ClientBuilder::connect`].
+    pub fn local_addr(mut self, ip: IpAddr) -> Self {
+        self.local_addr = Some(ip);
+        self
+    }
+
+    /// Makes the control connection and passive data connections through
+    /// `proxy`, only used by [`ClientBuilder::connect`]. Host names are left
+    /// for the proxy to resolve. Active mode fails with
+    /// [`io::ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported) as the
+    /// server can't connect back through the proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Whether passwords are hidden when commands are logged, on by default.
+    pub fn redact_logs(mut self, redact: bool) -> Self {
+        self.redact_logs = redact;
+        self
+    }
+
+    /// Secures the connection with AUTH TLS before returning it, see
+    /// [`Client::secure`].
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<ClientConfig>, domain: &str) -> Self {
+        self.tls = Some(Tls {
+            config,
+            domain: domain.to_owned(),
+            implicit: false,
+        });
+        self
+    }
+
+    /// Uses implicit FTPS, see [`Client::connect_implicit_tls`].
+    #[cfg(feature = "tls")]
+    pub fn implicit_tls(mut self, config: Arc<ClientConfig>, domain: &str) -> Self {
+        self.tls = Some(Tls {
+            config,
+            domain: domain.to_owned(),
+            implicit: true,
+        });
+        self
+    }
+
+    /// Connects to `addr`, reads the greeting and discovers what the server
+    /// supports.
+    pub fn connect(mut self, addr: impl ToTarget) -> Result<Client<Connected>> {
+        let target = addr.to_target()?;
+        let mut connector = TcpConnector::new();
+
+        if let Some(ip) = self.local_addr {
+            connector = connector.local_addr(ip);
+        }
+
+        if let Some(proxy) = self.proxy.take() {
+            connector = connector.proxy(proxy);
+        }
+
+        let (stream, peer) = connector
+            .connect_any(&target, self.timeouts.connect)
+            .map_err(|err| timeout(TimeoutPhase::Connect, err))?;
+
+        // Not the stream's peer, which could be a proxy
+        self.establish(stream, peer, connector)
+    }
+
+    /// Like [`ClientBuilder::connect`] but over `transport`, which is already
+    /// connected to the server, with data connections opened by `connector`.
+    pub fn connect_with<T: Transport, C: Connector>(
+        self,
+        transport: T,
+        connector: C,
+    ) -> Result<Client<Connected, T, C>> {
+        let peer = transport.peer_addr()?;
+
+        self.establish(transport, peer, connector)
+    }
+
+    fn establish<T: Transport, C: Connector>(
+        self,
+        transport: T,
+        peer: SocketAddr,
+        connector: C,
+    ) -> Result<Client<Connected, T, C>> {
+        #[cfg(feature = "tls")]
+        let tls = self
+            .tls
+            .map(|tls| Ok::<_, Error>((TlsConfig::new(tls.config, &tls.domain)?, tls.implicit)))
+            .transpose()?;
+
+        let stream = Stream::Plain(transport);
+
+        #[cfg(feature = "tls")]
+        let stream = match &tls {
+            Some((tls, true)) => {
+                // The handshake is bounded like a reply
+                stream.get_ref().set_read_timeout(self.timeouts.reply)?;
+                stream.into_tls(&tls.control, &tls.server_name)?
+            }
+            _ => stream,
+        };
+
+        let mut client = Client {
+            stream,
+            connector,
+            session: Session::with_config(peer, self.config),
+            read_buffer: vec![0; self.read_buffer_size],
+            data_buffer: Vec::new(),
+            data_buffer_size: self.data_buffer_size,
+            marker: PhantomData,
+            timeouts: self.timeouts,
+            redact_logs: self.redact_logs,
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+
+        client.execute()?;
+
+        #[cfg(feature = "tls")]
+        match tls {
+            Some((tls, true)) => {
+                client.tls = Some(tls);
+                client.session.protect_data();
+                client.execute()?;
+            }
+            Some((tls, false)) => client = client.upgrade(tls)?,
+            None => {}
+        }
+
+        Ok(client)
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientBuilder;
+
+    #[test]
+    #[should_panic(expected = "read buffer size must not be zero")]
+    fn test_zero_read_buffer_size() {
+        ClientBuilder::new().read_buffer_size(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "data buffer size must not be zero")]
+    fn test_zero_data_buffer_size() {
+        ClientBuilder::new().data_buffer_size(0);
+    }
+}
@@ -1,221 +1,515 @@
 use std::{
+    cmp::Ordering,
     error, fmt,
-    io::{BufRead, BufReader, BufWriter, Read, Write},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
-    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    net::TcpStream,
+    time::Instant,
 };
 
-use bytes::BytesMut;
 use fteepee_core::{
-    commands::{Command, Feat, List, Mlsd, Pass, Pasv, Stor, Type, User},
-    expect_code,
-    response::{ParsedResponseState, Response, ResponseExt},
-    Code, Config, Connected, Disconnected,
+    commands::redact,
+    response::Limits,
+    session::{Action, Output, Session},
+    Connected, DataMode, DirEntry, Disconnected,
 };
-use log::{log_enabled, trace};
+use log::{debug, log_enabled, trace};
 
-pub struct Client<State = Disconnected> {
-    stream: Lines<BufReader<TcpStream>>,
+pub use fteepee_core::{
+    ErrorKind, FileError, Proxy, Reply, Target, TimeoutPhase, Timeouts, ToTarget,
+};
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use rustls::ClientConfig;
+
+pub use crate::builder::ClientBuilder;
+pub use crate::transport::{Connector, TcpConnector, Transport};
+
+use crate::stream::Stream;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+
+mod builder;
+mod proxy;
+mod stream;
+#[cfg(feature = "tls")]
+mod tls;
+mod transport;
+
+/// An FTP client whose control connection is a `T` and whose data
+/// connections are opened by a `C`.
+pub struct Client<State = Disconnected, T: Transport = TcpStream, C = TcpConnector> {
+    stream: Stream<T>,
+    connector: C,
+    session: Session,
     read_buffer: Vec<u8>,
-    write_buffer: BytesMut,
     data_buffer: Vec<u8>,
-    response_buffer: Vec<u8>,
+    data_buffer_size: usize,
     marker: PhantomData<State>,
-    config: Config,
+    timeouts: Timeouts,
+    redact_logs: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
 }
 
 impl Client<Disconnected> {
-    pub fn connect(addr: impl ToSocketAddrs) -> Result<Client<Connected>> {
-        let stream = TcpStream::connect(addr)?;
+    /// Sets up a client with anything other than the defaults.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
 
-        let mut client = Client {
-            stream: Lines::new(BufReader::new(stream)),
-            read_buffer: Vec::new(),
-            write_buffer: BytesMut::new(),
-            data_buffer: Vec::new(),
-            response_buffer: Vec::new(),
-            marker: PhantomData,
-            config: Config::default(),
-        };
+    pub fn connect(addr: impl ToTarget) -> Result<Client<Connected>> {
+        ClientBuilder::new().connect(addr)
+    }
 
-        let resp = client.read_response()?;
-        expect_code!(resp.code()?, Code::READY);
+    /// Connects to a server using implicit FTPS, where TLS is negotiated as
+    /// soon as the connection is made, typically on port 990. All data
+    /// connections are protected as well.
+    ///
+    /// `domain` is the name the server's certificate is verified against.
+    #[cfg(feature = "tls")]
+    pub fn connect_implicit_tls(
+        addr: impl ToTarget,
+        config: Arc<ClientConfig>,
+        domain: &str,
+    ) -> Result<Client<Connected>> {
+        ClientBuilder::new()
+            .implicit_tls(config, domain)
+            .connect(addr)
+    }
+}
 
-        let cmd = Feat;
-        client.write_request(&cmd)?;
-        let resp = client.read_response()?;
-        expect_code!(
-            resp.code()?,
-            Code::SYSTEM_STATUS | Code::UNRECOGNIZED_COMMAND | Code::NOT_IMPLEMENTED
-        );
+impl<T: Transport, C: Connector> Client<Connected, T, C> {
+    /// Logs in, returning the server's final reply which often carries a
+    /// message of the day.
+    pub fn login(&mut self, user: &str, pass: &str) -> Result<Reply> {
+        self.session.login(user, pass);
+
+        match self.execute()? {
+            Output::Reply(reply) => Ok(reply),
+            output => unreachable!("login finished with {:?}", output),
+        }
+    }
 
-        if matches!(resp.code()?, Code::SYSTEM_STATUS) {
-            let features = fteepee_core::parse_features(&client.response_buffer);
+    /// The server's greeting.
+    pub fn welcome(&self) -> &Reply {
+        self.session
+            .welcome()
+            .expect("connected without a greeting")
+    }
 
-            if features.get("MLST").is_some() {
-                client.config.mlst_supported = true;
+    pub fn list(&mut self, path: &str) -> Result<Vec<DirEntry>> {
+        self.session.list(path);
+        let stream = self.transfer()?;
+
+        let mut lines = Lines::new(BufReader::with_capacity(self.data_buffer_size, stream));
+        let mut entries = Vec::new();
+
+        let result = loop {
+            let line = match lines.next(&mut self.data_buffer) {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => break Err(data_timeout(err)),
+                None => break Ok(()),
+            };
+
+            match self.session.parse_entry(line) {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => {}
+                Err(err) => debug!(
+                    "skipping listing line {:?}: {}",
+                    String::from_utf8_lossy(line),
+                    err
+                ),
             }
         };
 
-        Ok(client)
+        // We are done with this connection
+        drop(lines);
+        self.finish_transfer(result)?;
+
+        Ok(entries)
     }
-}
 
-impl Client<Connected> {
-    pub fn login(&mut self, user: &str, pass: &str) -> Result<()> {
-        let cmd = User::new(user);
+    pub fn get<W: Write>(&mut self, path: &str, writer: &mut W) -> Result<()> {
+        self.get_from(path, 0, writer)
+    }
 
-        self.write_request(&cmd)?;
-        let resp = self.read_response()?;
-        expect_code!(resp.code()?, Code::LOGGED_IN | Code::PASSWORD_REQUIRED);
+    /// Downloads `path` starting `offset` bytes in, only the remainder of the
+    /// file is written to `writer`.
+    pub fn get_from<W: Write>(&mut self, path: &str, offset: u64, writer: &mut W) -> Result<()> {
+        self.session.retrieve(path, offset);
+        let mut stream = BufReader::with_capacity(self.data_buffer_size, self.transfer()?);
 
-        let cmd = Pass::new(pass);
+        let result = std::io::copy(&mut stream, writer).map_err(data_timeout);
 
-        self.write_request(&cmd)?;
-        let resp = self.read_response()?;
-        expect_code!(resp.code()?, Code::LOGGED_IN);
+        // We are done with this connection
+        drop(stream);
+        self.finish_transfer(result)?;
 
         Ok(())
     }
 
-    pub fn list(&mut self, path: &str) -> Result<()> {
-        let stream = if self.config.mlst_supported {
-            let cmd = Mlsd::new(path);
-            BufReader::new(self.data_connection(&cmd)?)
-        } else {
-            let cmd = List::new(path);
-            BufReader::new(self.data_connection(&cmd)?)
-        };
+    pub fn put<R: Read>(&mut self, path: &str, reader: &mut R) -> Result<()> {
+        self.put_from(path, 0, reader)
+    }
+
+    /// Uploads `reader` to `path` starting `offset` bytes in, `reader` should
+    /// already be positioned at `offset`.
+    pub fn put_from<R: Read>(&mut self, path: &str, offset: u64, reader: &mut R) -> Result<()> {
+        self.session.store(path, offset);
+        let mut stream = BufWriter::with_capacity(self.data_buffer_size, self.transfer()?);
+
+        let result = std::io::copy(reader, &mut stream)
+            .map_err(data_timeout)
+            .and_then(|_| {
+                // We are done with this connection
+                let mut stream = stream
+                    .into_inner()
+                    .map_err(|err| data_timeout(err.into_error()))?;
 
-        let resp = self.read_response()?;
-        expect_code!(resp.code()?, Code::OPENING_DATA_CONNECTION);
+                stream.shutdown().map_err(data_timeout)
+            });
 
-        let mut lines = Lines::new(stream);
+        self.finish_transfer(result)?;
 
-        while let Some(line) = lines.next(&mut self.data_buffer) {
-            let line = line?;
+        Ok(())
+    }
 
-            dbg!(String::from_utf8_lossy(line));
+    /// Continues downloading `path` into `local`, only fetching the bytes that
+    /// are not already there.
+    pub fn resume_download<W: Write + Seek>(&mut self, path: &str, local: &mut W) -> Result<()> {
+        let size = self.size(path)?;
+        let offset = local.seek(SeekFrom::End(0))?;
+
+        match offset.cmp(&size) {
+            Ordering::Less => self.get_from(path, offset, local),
+            Ordering::Equal => Ok(()),
+            Ordering::Greater => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "local file is larger than the remote file",
+            )
+            .into()),
         }
+    }
 
-        let resp = self.read_response()?;
-        expect_code!(
-            resp.code()?,
-            Code::CLOSING_DATA_CONNECTION | Code::REQUESTED_FILE_ACTION_OKAY,
-        );
+    /// Returns the size of `path` in bytes.
+    pub fn size(&mut self, path: &str) -> Result<u64> {
+        self.session.size(path);
+
+        match self.execute()? {
+            Output::Size(size) => Ok(size),
+            output => unreachable!("SIZE finished with {:?}", output),
+        }
+    }
+
+    /// Changes the working directory to `path`.
+    pub fn cwd(&mut self, path: &str) -> Result<()> {
+        self.session.cwd(path);
+        self.execute()?;
 
         Ok(())
     }
 
-    pub fn put<R: Read>(&mut self, path: &str, reader: &mut R) -> Result<()> {
-        let cmd = Type::Image;
+    /// Changes the working directory to its parent.
+    pub fn cdup(&mut self) -> Result<()> {
+        self.session.cdup();
+        self.execute()?;
+
+        Ok(())
+    }
 
-        self.write_request(&cmd)?;
-        let resp = self.read_response()?;
-        expect_code!(resp.code()?, Code::COMMAND_OKAY);
+    /// Returns the working directory.
+    pub fn pwd(&mut self) -> Result<String> {
+        self.session.pwd();
 
-        let cmd = Stor::new(path);
-        let mut stream = BufWriter::new(self.data_connection(&cmd)?);
+        match self.execute()? {
+            Output::Pathname(pathname) => Ok(pathname),
+            output => unreachable!("PWD finished with {:?}", output),
+        }
+    }
 
-        let resp = self.read_response()?;
-        expect_code!(
-            resp.code()?,
-            Code::DATA_CONNECTION_ALREADY_OPEN | Code::OPENING_DATA_CONNECTION
-        );
+    pub fn mkdir(&mut self, path: &str) -> Result<()> {
+        self.session.mkdir(path);
+        self.execute()?;
 
-        std::io::copy(reader, &mut stream)?;
+        Ok(())
+    }
 
-        // We are done with this connection
-        drop(stream);
+    pub fn rmdir(&mut self, path: &str) -> Result<()> {
+        self.session.rmdir(path);
+        self.execute()?;
 
-        let resp = self.read_response()?;
-        expect_code!(
-            resp.code()?,
-            Code::CLOSING_DATA_CONNECTION | Code::REQUESTED_FILE_ACTION_OKAY,
-        );
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> Result<()> {
+        self.session.remove_file(path);
+        self.execute()?;
 
         Ok(())
     }
 
-    fn data_connection<C: Command>(&mut self, cmd: &C) -> Result<TcpStream> {
-        let addr = self.pasv()?;
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.session.rename(from, to);
+        self.execute()?;
+
+        Ok(())
+    }
 
-        self.write_request(cmd)?;
+    pub fn set_data_mode(&mut self, mode: DataMode) {
+        self.session.set_data_mode(mode);
+    }
 
-        let stream = TcpStream::connect(addr)?;
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
 
-        Ok(stream)
+    /// Bounds the size of replies, which fail with
+    /// [`fteepee_core::Error::ReplyTooLarge`] when they go over.
+    pub fn set_reply_limits(&mut self, limits: Limits) {
+        self.session.set_reply_limits(limits);
     }
 
-    fn pasv(&mut self) -> Result<SocketAddr> {
-        let cmd = Pasv;
+    /// Reads the server's reply to a transfer once the data connection is
+    /// closed. The reply to a failed transfer is read too, so the next command
+    /// doesn't get it, but the transfer's error is what is returned.
+    fn finish_transfer<R>(&mut self, result: Result<R>) -> Result<R> {
+        let output = match result {
+            Ok(output) => output,
+            Err(err) => {
+                self.session.transfer_failed();
+
+                if let Err(reply_err) = self.execute() {
+                    debug!("no reply to failed transfer: {}", reply_err);
+                }
+
+                return Err(err);
+            }
+        };
+
+        self.session.transfer_complete();
+        self.execute()?;
 
-        self.write_request(&cmd)?;
-        let mut resp = self.read_response()?;
-        expect_code!(resp.code()?, Code::ENTERING_PASSIVE_MODE);
+        Ok(output)
+    }
+}
 
-        Ok(SocketAddr::from(
-            resp.parse_passive_mode(&self.response_buffer)?,
-        ))
+#[cfg(feature = "tls")]
+impl<T: Transport, C: Connector> Client<Connected, T, C> {
+    /// Upgrades the control connection to TLS using AUTH TLS and protects all
+    /// subsequent data connections, this should be done before logging in.
+    /// Data connections resume the control connection's TLS session.
+    ///
+    /// `domain` is the name the server's certificate is verified against.
+    pub fn secure(self, config: Arc<ClientConfig>, domain: &str) -> Result<Self> {
+        let tls = TlsConfig::new(config, domain)?;
+
+        self.upgrade(tls)
     }
 
-    fn read_response(&mut self) -> Result<Response> {
-        self.read_buffer.clear();
+    fn upgrade(mut self, tls: TlsConfig) -> Result<Self> {
+        self.session.auth_tls();
+        self.execute()?;
 
-        let mut parsed_response = Response::new();
+        // The handshake is bounded like a reply
+        self.stream
+            .get_ref()
+            .set_read_timeout(self.timeouts.reply)?;
 
-        while let Some(line) = self.stream.next(&mut self.read_buffer) {
-            let line = line?;
-            self.response_buffer
-                .resize(self.response_buffer.len() + line.len(), 0);
+        let mut client = Client {
+            stream: self.stream.into_tls(&tls.control, &tls.server_name)?,
+            tls: Some(tls),
+            ..self
+        };
 
-            let state = parsed_response.read_bytes(line, &mut self.response_buffer)?;
+        client.session.protect_data();
+        client.execute()?;
+
+        Ok(client)
+    }
+}
 
-            // FIXME: I will block forever if I don't reach a completed state
-            if matches!(state, ParsedResponseState::Complete) {
-                break;
+impl<State, T: Transport, C: Connector> Client<State, T, C> {
+    /// Drives the session until the current operation finishes or its data
+    /// connection is ready for the transfer.
+    fn run(&mut self) -> Result<Progress<C::Stream>> {
+        let mut data = None;
+        // When the reply being waited for is due
+        let mut deadline = None;
+
+        loop {
+            match self.session.poll()? {
+                Action::Send(bytes) => {
+                    self.stream.write_all(bytes)?;
+                    deadline = None;
+
+                    if log_enabled!(log::Level::Trace) {
+                        let bytes = if self.redact_logs {
+                            redact(bytes)
+                        } else {
+                            bytes.into()
+                        };
+
+                        trace!("--> {}", String::from_utf8_lossy(&bytes));
+                    }
+                }
+                Action::Receive => {
+                    let remaining = match self.timeouts.reply {
+                        Some(timeout) => {
+                            let deadline =
+                                *deadline.get_or_insert_with(|| Instant::now() + timeout);
+                            let remaining = deadline.saturating_duration_since(Instant::now());
+
+                            if remaining.is_zero() {
+                                return Err(Error::Timeout(TimeoutPhase::Reply));
+                            }
+
+                            Some(remaining)
+                        }
+                        None => None,
+                    };
+
+                    // Always set, an earlier reply or the TLS handshake may
+                    // have left a shorter one behind
+                    self.stream.get_ref().set_read_timeout(remaining)?;
+
+                    let n = match self.stream.read(&mut self.read_buffer) {
+                        Ok(n) => n,
+                        Err(err) if is_timeout(&err) => {
+                            return Err(Error::Timeout(TimeoutPhase::Reply))
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "control connection closed",
+                        )
+                        .into());
+                    }
+
+                    if log_enabled!(log::Level::Trace) {
+                        trace!("<-- {}", String::from_utf8_lossy(&self.read_buffer[..n]));
+                    }
+
+                    self.session.receive(&self.read_buffer[..n]);
+                }
+                Action::Connect(addr) => {
+                    let stream = self
+                        .connector
+                        .connect(addr, self.timeouts.connect)
+                        .map_err(|err| timeout(TimeoutPhase::Connect, err))?;
+
+                    data = Some(self.data_stream(stream)?);
+                    self.session.data_connected();
+                }
+                Action::Listen(ports) => {
+                    let ip = self.stream.get_ref().local_addr()?.ip();
+                    let addr = self.connector.listen(ip, ports)?;
+
+                    self.session.listening(addr);
+                }
+                Action::Accept => {
+                    let peer = self.stream.get_ref().peer_addr()?.ip();
+                    let stream = self
+                        .connector
+                        .accept(peer, self.timeouts.data_idle)
+                        .map_err(data_timeout)?;
+
+                    data = Some(self.data_stream(stream)?);
+                    self.session.data_connected();
+                }
+                Action::Transfer => {
+                    let stream = data.take().expect("transferring without a connection");
+
+                    return Ok(Progress::Transfer(stream));
+                }
+                Action::Done(output) => return Ok(Progress::Done(output)),
             }
         }
+    }
 
-        if log_enabled!(log::Level::Trace) {
-            trace!(
-                "<-- {}",
-                String::from_utf8_lossy(parsed_response.message(&self.response_buffer))
-            );
+    /// Runs an operation that does not transfer any data.
+    fn execute(&mut self) -> Result<Output> {
+        match self.run()? {
+            Progress::Done(output) => Ok(output),
+            Progress::Transfer(_) => unreachable!("operation opened a data connection"),
         }
+    }
 
-        Ok(parsed_response)
+    /// Runs a transfer operation up to the point the data can be sent.
+    fn transfer(&mut self) -> Result<Stream<C::Stream>> {
+        match self.run()? {
+            Progress::Transfer(stream) => Ok(stream),
+            Progress::Done(_) => unreachable!("transfer finished without a data connection"),
+        }
     }
 
-    fn write_request<C: Command>(&mut self, cmd: &C) -> Result<()> {
-        self.write_buffer.clear();
+    fn data_stream(&self, stream: C::Stream) -> Result<Stream<C::Stream>> {
+        stream.set_read_timeout(self.timeouts.data_idle)?;
+        stream.set_write_timeout(self.timeouts.data_idle)?;
 
-        self.write_buffer.resize(cmd.size(), 0);
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return Stream::Plain(stream)
+                .into_tls(&tls.data, &tls.server_name)
+                .map_err(data_timeout);
+        }
 
-        cmd.encode(&mut self.write_buffer);
+        Ok(Stream::Plain(stream))
+    }
+}
 
-        self.stream
-            .reader
-            .get_mut()
-            .write_all(&self.write_buffer[..cmd.size()])?;
-
-        if log_enabled!(log::Level::Trace) {
-            trace!(
-                "--> {}",
-                String::from_utf8_lossy(&self.write_buffer[..cmd.size()])
-            );
-        }
+/// Socket timeouts fail with either kind depending on the platform. Our own
+/// errors passed back by a connector keep their kind and are not timeouts.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ) && !err.get_ref().is_some_and(|err| err.is::<Error>())
+}
 
-        Ok(())
+/// Reports a socket that gave up waiting as having timed out during `phase`.
+fn timeout(phase: TimeoutPhase, err: impl Into<Error>) -> Error {
+    match err.into() {
+        Error::IO(err) if is_timeout(&err) => Error::Timeout(phase),
+        err => err,
     }
 }
 
+/// Reports a data connection that went idle for too long as a timeout.
+fn data_timeout(err: impl Into<Error>) -> Error {
+    timeout(TimeoutPhase::Data, err)
+}
+
+const READ_BUFFER_SIZE: usize = 4096;
+const DATA_BUFFER_SIZE: usize = 8 * 1024;
+
+enum Progress<S: Transport> {
+    Transfer(Stream<S>),
+    Done(Output),
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
     IO(std::io::Error),
     Internal(fteepee_core::Error),
+    /// The server refused to act on `path`.
+    File {
+        kind: FileError,
+        path: String,
+        message: String,
+    },
+    /// Gave up waiting, see [`Timeouts`].
+    Timeout(TimeoutPhase),
+    #[cfg(feature = "tls")]
+    Tls(rustls::Error),
 }
 
 impl fmt::Display for Error {
@@ -223,6 +517,14 @@ impl fmt::Display for Error {
         match self {
             Error::IO(err) => err.fmt(f),
             Error::Internal(err) => err.fmt(f),
+            Error::File {
+                kind,
+                path,
+                message,
+            } => write!(f, "{:?} {}: {}", path, kind, message),
+            Error::Timeout(phase) => write!(f, "timed out {}", phase),
+            #[cfg(feature = "tls")]
+            Error::Tls(err) => err.fmt(f),
         }
     }
 }
@@ -232,6 +534,43 @@ impl error::Error for Error {
         match self {
             Error::IO(err) => Some(err),
             Error::Internal(err) => Some(err),
+            Error::File { .. } | Error::Timeout(_) => None,
+            #[cfg(feature = "tls")]
+            Error::Tls(err) => Some(err),
+        }
+    }
+}
+
+impl Error {
+    /// Categorizes the error, e.g. to decide whether to retry.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            // Connectors can only fail with I/O errors
+            Error::IO(err) => match err.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+                Some(err) => err.kind(),
+                None => err.kind().into(),
+            },
+            Error::Internal(err) => err.kind(),
+            Error::File { kind, message, .. } => ErrorKind::from_file_error(*kind, message),
+            Error::Timeout(_) => ErrorKind::Transient,
+            #[cfg(feature = "tls")]
+            Error::Tls(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Whether the operation could succeed if it is tried again, possibly on
+    /// a new connection.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::IO(err) => err,
+            err @ Error::Timeout(_) => io::Error::new(io::ErrorKind::TimedOut, err),
+            err => io::Error::new(err.kind().into(), err),
         }
     }
 }
@@ -244,7 +583,25 @@ impl From<std::io::Error> for Error {
 
 impl From<fteepee_core::Error> for Error {
     fn from(err: fteepee_core::Error) -> Self {
-        Self::Internal(err)
+        match err {
+            fteepee_core::Error::File {
+                kind,
+                path,
+                message,
+            } => Self::File {
+                kind,
+                path,
+                message,
+            },
+            err => Self::Internal(err),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Self {
+        Self::Tls(err)
     }
 }
 
@@ -274,3 +631,267 @@ impl<B: BufRead> Lines<B> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Cursor, Read, Write},
+        net::{SocketAddr, TcpListener},
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::{Client, Connector, DataMode, Error, TimeoutPhase, Timeouts, Transport};
+
+    /// Plays the server's side of a connection from a script, keeping what
+    /// the client sends.
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Script {
+        fn new(input: &str) -> (Self, Arc<Mutex<Vec<u8>>>) {
+            let output = Arc::default();
+            let script = Self {
+                input: Cursor::new(input.as_bytes().to_vec()),
+                output: Arc::clone(&output),
+            };
+
+            (script, output)
+        }
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Script {
+        fn peer_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([127, 0, 0, 1], 21)))
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([127, 0, 0, 1], 50000)))
+        }
+    }
+
+    /// Opens every data connection to the same script.
+    struct Scripted(&'static str);
+
+    impl Connector for Scripted {
+        type Stream = Script;
+
+        fn connect(&mut self, addr: SocketAddr, _: Option<Duration>) -> io::Result<Script> {
+            assert_eq!(addr, SocketAddr::from(([127, 0, 0, 1], 1025)));
+
+            Ok(Script::new(self.0).0)
+        }
+    }
+
+    /// Writes `script` to whoever connects and then goes quiet until they
+    /// hang up, timeouts need a real socket.
+    fn stall(script: String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(script.as_bytes()).unwrap();
+            let _ = io::copy(&mut stream, &mut io::sink());
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_connect_with() {
+        let (control, sent) = Script::new(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "331 Password required\r\n",
+            "230 Logged in\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "226 Done\r\n",
+        ));
+        let connector = Scripted("-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n");
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, connector)
+            .unwrap();
+
+        client.login("user", "pass").unwrap();
+        let entries = client.list("/").unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a.txt");
+        assert_eq!(
+            String::from_utf8_lossy(&sent.lock().unwrap()),
+            "SYST\r\nUSER user\r\nPASS pass\r\nPASV\r\nLIST /\r\n"
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let (control, sent) = Script::new(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "200 Type set to I\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "226 Done\r\n",
+        ));
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, Scripted("hello world"))
+            .unwrap();
+
+        let mut file = Vec::new();
+        client.get("a.txt", &mut file).unwrap();
+
+        assert_eq!(file, b"hello world");
+        assert_eq!(
+            String::from_utf8_lossy(&sent.lock().unwrap()),
+            "SYST\r\nTYPE I\r\nPASV\r\nRETR a.txt\r\n"
+        );
+    }
+
+    #[test]
+    fn test_failed_get() {
+        let (control, sent) = Script::new(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "200 Type set to I\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "426 Connection closed; transfer aborted\r\n",
+            "257 \"/\" is the current directory\r\n",
+        ));
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, Scripted("hello world"))
+            .unwrap();
+
+        // Nothing fits, so writing the file fails part way
+        let mut full: &mut [u8] = &mut [];
+        let err = client.get("a.txt", &mut full).unwrap_err();
+
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::WriteZero);
+        // The reply to the transfer is not mistaken for the one to PWD
+        assert_eq!(client.pwd().unwrap(), "/");
+        assert_eq!(
+            String::from_utf8_lossy(&sent.lock().unwrap()),
+            "SYST\r\nTYPE I\r\nPASV\r\nRETR a.txt\r\nPWD\r\n"
+        );
+    }
+
+    #[test]
+    fn test_reply_timeout() {
+        let addr = stall("220 Ready\r\n215 UNIX Type: L8\r\n".into());
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .reply_timeout(Duration::from_millis(50))
+            .connect(addr)
+            .unwrap();
+
+        assert!(matches!(
+            client.pwd(),
+            Err(Error::Timeout(TimeoutPhase::Reply))
+        ));
+    }
+
+    #[test]
+    fn test_reply_timeout_cleared() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"220 Ready\r\n215 UNIX Type: L8\r\n")
+                .unwrap();
+
+            // Slower than the timeout used for the greeting
+            let mut pwd = [0; 5];
+            stream.read_exact(&mut pwd).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stream.write_all(b"257 \"/\"\r\n").unwrap();
+
+            let _ = io::copy(&mut stream, &mut io::sink());
+        });
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .reply_timeout(Duration::from_millis(100))
+            .connect(addr)
+            .unwrap();
+
+        client.set_timeouts(Timeouts::default());
+
+        assert_eq!(client.pwd().unwrap(), "/");
+    }
+
+    #[test]
+    fn test_data_timeout() {
+        let data = stall(String::new());
+        let addr = stall(format!(
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "200 Type set to I\r\n",
+                "227 Entering Passive Mode (127,0,0,1,{},{})\r\n",
+                "150 Opening\r\n",
+                "426 Connection closed; transfer aborted\r\n",
+            ),
+            data.port() >> 8,
+            data.port() & 0xff
+        ));
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_idle_timeout(Duration::from_millis(50))
+            .connect(addr)
+            .unwrap();
+
+        assert!(matches!(
+            client.get("a.txt", &mut Vec::new()),
+            Err(Error::Timeout(TimeoutPhase::Data))
+        ));
+    }
+
+    #[test]
+    fn test_active_unsupported() {
+        let (control, _) = Script::new("220 Ready\r\n215 UNIX Type: L8\r\n");
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_mode(DataMode::Active {
+                external_ip: None,
+                ports: None,
+            })
+            .connect_with(control, Scripted(""))
+            .unwrap();
+
+        let err = client.list("/").unwrap_err();
+
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+    }
+}
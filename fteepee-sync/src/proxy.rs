@@ -0,0 +1,351 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, TcpStream},
+    time::Duration,
+};
+
+use fteepee_core::{
+    proxy::{http, socks5, ProxyKind},
+    ProxyError,
+};
+
+use crate::{transport, Proxy, Result, Target};
+
+/// Connects to `target` through `proxy`, `timeout` bounds the handshake with
+/// the proxy as well.
+pub(crate) fn connect(
+    proxy: &Proxy,
+    target: &Target,
+    timeout: Option<Duration>,
+    local: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+    let mut stream = transport::connect(proxy.addr.as_str(), timeout, local)?;
+
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    match proxy.kind {
+        ProxyKind::Socks5 => connect_socks5(&mut stream, proxy, target)?,
+        ProxyKind::Http => connect_http(&mut stream, proxy, target)?,
+    }
+
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+
+    Ok(stream)
+}
+
+fn connect_socks5(stream: &mut TcpStream, proxy: &Proxy, target: &Target) -> Result<()> {
+    let credentials = proxy.credentials.as_ref();
+
+    stream.write_all(&socks5::greeting(credentials))?;
+
+    if socks5::parse_method(read_array(stream)?)? {
+        // Only offered with credentials
+        let credentials =
+            credentials.ok_or(fteepee_core::Error::Proxy(ProxyError::InvalidReply))?;
+
+        stream.write_all(&socks5::auth_request(credentials)?)?;
+        socks5::parse_auth(read_array(stream)?)?;
+    }
+
+    stream.write_all(&socks5::connect_request(target)?)?;
+    let remaining = socks5::parse_connect(read_array(stream)?)?;
+
+    // The address the proxy connected from is of no use
+    stream.read_exact(&mut vec![0; remaining])?;
+
+    Ok(())
+}
+
+fn connect_http(stream: &mut TcpStream, proxy: &Proxy, target: &Target) -> Result<()> {
+    stream.write_all(&http::connect_request(target, proxy.credentials.as_ref()))?;
+
+    let mut response = Vec::new();
+
+    // A byte at a time so that none of the server's greeting is read
+    while !http::is_complete(&response) {
+        if response.len() == http::MAX_RESPONSE_SIZE {
+            return Err(fteepee_core::Error::Proxy(ProxyError::InvalidReply).into());
+        }
+
+        response.push(read_array::<1>(stream)?[0]);
+    }
+
+    http::parse_response(&response)?;
+
+    Ok(())
+}
+
+fn read_array<const N: usize>(stream: &mut TcpStream) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    stream.read_exact(&mut buf)?;
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        thread::{self, JoinHandle},
+    };
+
+    use fteepee_core::DataMode;
+
+    use crate::{Client, Proxy};
+
+    /// Stands in for a proxy, and for the server behind it once each
+    /// connection's handshake is done. Returns what the client sent on the
+    /// control connection.
+    fn stand_in(
+        handshakes: Vec<fn(&mut TcpStream)>,
+        replies: &'static str,
+        listing: &'static str,
+    ) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut handshakes = handshakes.into_iter();
+
+            let (mut control, _) = listener.accept().unwrap();
+            handshakes.next().unwrap()(&mut control);
+            control.write_all(replies.as_bytes()).unwrap();
+
+            for handshake in handshakes {
+                let (mut data, _) = listener.accept().unwrap();
+                handshake(&mut data);
+                data.write_all(listing.as_bytes()).unwrap();
+            }
+
+            let mut sent = String::new();
+            control.read_to_string(&mut sent).unwrap();
+            sent
+        });
+
+        (addr, handle)
+    }
+
+    fn expect(stream: &mut TcpStream, expected: &[u8]) {
+        let mut buf = vec![0; expected.len()];
+        stream.read_exact(&mut buf).unwrap();
+
+        assert_eq!(buf, expected);
+    }
+
+    /// `target` starts with the address type.
+    fn socks5_handshake(stream: &mut TcpStream, target: &[u8]) {
+        expect(stream, &[5, 2, 0, 2]);
+        stream.write_all(&[5, 2]).unwrap();
+        expect(stream, b"\x01\x04user\x04pass");
+        stream.write_all(&[1, 0]).unwrap();
+        expect(stream, &[[5, 1, 0].as_slice(), target].concat());
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 4, 1]).unwrap();
+    }
+
+    fn http_handshake(stream: &mut TcpStream, target: &str) {
+        expect(
+            stream,
+            format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n").as_bytes(),
+        );
+        stream
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_socks5() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                |stream| socks5_handshake(stream, &[1, 192, 0, 2, 1, 0, 21]),
+                // EPSV connects to the same host as the control connection
+                |stream| socks5_handshake(stream, &[1, 192, 0, 2, 1, 4, 1]),
+                |stream| socks5_handshake(stream, &[1, 192, 0, 2, 1, 4, 2]),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "211-Features:\r\n EPSV\r\n211 End\r\n",
+                "215 UNIX Type: L8\r\n",
+                "229 Entering Extended Passive Mode (|||1025|)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+                "500 EPSV disabled\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        );
+
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .proxy(proxy)
+            .connect("192.0.2.1:21")
+            .unwrap();
+
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+        // Falls back to PASV, which only takes the port from the reply
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(
+            stand_in.join().unwrap(),
+            "FEAT\r\nSYST\r\nEPSV\r\nLIST /\r\nEPSV\r\nPASV\r\nLIST /\r\n"
+        );
+    }
+
+    #[test]
+    fn test_socks5_host() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                |stream| socks5_handshake(stream, b"\x03\x0bftp.invalid\x00\x15"),
+                |stream| socks5_handshake(stream, b"\x03\x0bftp.invalid\x04\x01"),
+                |stream| socks5_handshake(stream, b"\x03\x0bftp.invalid\x04\x02"),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "211-Features:\r\n EPSV\r\n211 End\r\n",
+                "215 UNIX Type: L8\r\n",
+                "229 Entering Extended Passive Mode (|||1025|)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+                "500 EPSV disabled\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        );
+
+        // Can't be resolved locally, only the proxy is given the name
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .proxy(proxy)
+            .connect("ftp.invalid:21")
+            .unwrap();
+
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(
+            stand_in.join().unwrap(),
+            "FEAT\r\nSYST\r\nEPSV\r\nLIST /\r\nEPSV\r\nPASV\r\nLIST /\r\n"
+        );
+    }
+
+    #[test]
+    fn test_http() {
+        let (addr, stand_in) = stand_in(
+            vec![|stream| http_handshake(stream, "192.0.2.1:21"), |stream| {
+                http_handshake(stream, "192.0.2.1:1026")
+            }],
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        );
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .proxy(Proxy::http(addr.to_string()))
+            .connect("192.0.2.1:21")
+            .unwrap();
+
+        // The greeting is not mistaken for part of the proxy's response
+        assert_eq!(client.welcome().message(), "Ready");
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(stand_in.join().unwrap(), "SYST\r\nPASV\r\nLIST /\r\n");
+    }
+
+    #[test]
+    fn test_http_host() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                |stream| http_handshake(stream, "ftp.invalid:21"),
+                |stream| http_handshake(stream, "ftp.invalid:1026"),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        );
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .proxy(Proxy::http(addr.to_string()))
+            .connect(("ftp.invalid", 21))
+            .unwrap();
+
+        assert_eq!(client.list("/").unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(stand_in.join().unwrap(), "SYST\r\nPASV\r\nLIST /\r\n");
+    }
+
+    #[test]
+    fn test_active() {
+        let (addr, stand_in) = stand_in(
+            vec![|stream| socks5_handshake(stream, &[1, 192, 0, 2, 1, 0, 21])],
+            "220 Ready\r\n215 UNIX Type: L8\r\n",
+            "",
+        );
+
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_mode(DataMode::Active {
+                external_ip: None,
+                ports: None,
+            })
+            .proxy(proxy)
+            .connect("192.0.2.1:21")
+            .unwrap();
+
+        // The server can't connect back through the proxy
+        let err = client.list("/").unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+
+        drop(client);
+        assert_eq!(stand_in.join().unwrap(), "SYST\r\n");
+    }
+
+    #[test]
+    fn test_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+            expect(&mut stream, &[5, 1, 0, 1, 192, 0, 2, 1, 0, 21]);
+            stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        });
+
+        let err = Client::builder()
+            .proxy(Proxy::socks5(addr.to_string()))
+            .connect("192.0.2.1:21")
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            "SOCKS5 proxy failed to connect: connection refused"
+        );
+        assert!(err.is_retryable());
+        stand_in.join().unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, ProtocolVersion, StreamOwned};
+
+use crate::Transport;
+
+#[cfg(feature = "tls")]
+use crate::is_timeout;
+
+/// A control or data connection, optionally protected by TLS.
+pub(crate) enum Stream<T: Transport> {
+    Plain(T),
+    #[cfg(feature = "tls")]
+    Tls(Box<StreamOwned<ClientConnection, T>>),
+}
+
+impl<T: Transport> Stream<T> {
+    pub(crate) fn get_ref(&self) -> &T {
+        match self {
+            Stream::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.get_ref(),
+        }
+    }
+
+    /// Performs a TLS handshake over a plain connection.
+    #[cfg(feature = "tls")]
+    pub(crate) fn into_tls(
+        self,
+        config: &Arc<ClientConfig>,
+        server_name: &ServerName<'static>,
+    ) -> crate::Result<Self> {
+        let mut stream = match self {
+            Stream::Plain(stream) => stream,
+            Stream::Tls(_) => return Ok(self),
+        };
+
+        let mut conn = ClientConnection::new(Arc::clone(config), server_name.clone())?;
+
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+
+        Ok(Stream::Tls(Box::new(StreamOwned::new(conn, stream))))
+    }
+
+    /// Flushes any pending data, notifying the server that we are done
+    /// writing when using TLS.
+    pub(crate) fn shutdown(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()?;
+
+                // TLS 1.3 session tickets arrive after the handshake and are
+                // only seen when reading, later data connections need one to
+                // resume the session. Reading stops once there is one or the
+                // server closes, bounded by the data idle timeout.
+                let StreamOwned { conn, sock } = &mut **stream;
+
+                while conn.protocol_version() == Some(ProtocolVersion::TLSv1_3)
+                    && conn.tls13_tickets_received() == 0
+                {
+                    match conn.read_tls(sock) {
+                        Ok(0) => break,
+                        Ok(_) => {
+                            let state = conn
+                                .process_new_packets()
+                                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+                            if state.peer_has_closed() {
+                                break;
+                            }
+                        }
+                        Err(err)
+                            if err.kind() == io::ErrorKind::UnexpectedEof || is_timeout(&err) =>
+                        {
+                            break
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T: Transport> Read for Stream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl<T: Transport> Write for Stream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    ops::RangeInclusive,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{proxy, Proxy, Target};
+
+/// A connection to the server that commands or data are sent over.
+pub trait Transport: Read + Write {
+    /// Address of the server, passive data connections are made to its IP.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Address of this end of the connection, active mode listens on its IP.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Bounds how long a read may block, failing with
+    /// [`io::ErrorKind::TimedOut`] or [`io::ErrorKind::WouldBlock`] once it
+    /// passes. Transports that cannot time out ignore it.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let _ = timeout;
+        Ok(())
+    }
+
+    /// Like [`Transport::set_read_timeout`] but for writes.
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let _ = timeout;
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Opens data connections, e.g. to tunnel them or to set socket options.
+pub trait Connector {
+    type Stream: Transport;
+
+    /// Connects to `addr` for a passive transfer, failing with
+    /// [`io::ErrorKind::TimedOut`] once `timeout` passes.
+    fn connect(&mut self, addr: SocketAddr, timeout: Option<Duration>) -> io::Result<Self::Stream>;
+
+    /// Starts listening for an active transfer on `ip`, using one of `ports`
+    /// if given, and returns the address the server should connect to.
+    ///
+    /// Active mode is unsupported unless this and [`Connector::accept`] are
+    /// implemented.
+    fn listen(&mut self, ip: IpAddr, ports: Option<RangeInclusive<u16>>) -> io::Result<SocketAddr> {
+        let _ = (ip, ports);
+        Err(active_unsupported())
+    }
+
+    /// Accepts the server's connection to the address returned by
+    /// [`Connector::listen`], failing with [`io::ErrorKind::TimedOut`] once
+    /// `timeout` passes. Connections from any IP other than `peer`, the
+    /// server's, should be turned away.
+    fn accept(&mut self, peer: IpAddr, timeout: Option<Duration>) -> io::Result<Self::Stream> {
+        let _ = (peer, timeout);
+        Err(active_unsupported())
+    }
+}
+
+/// Opens data connections over TCP, which is what clients connected with
+/// [`ClientBuilder::connect`](crate::ClientBuilder::connect) use.
+#[derive(Debug, Default)]
+pub struct TcpConnector {
+    local_addr: Option<IpAddr>,
+    proxy: Option<Proxy>,
+    /// The server's name when the proxy resolves it, see
+    /// [`TcpConnector::connect_any`].
+    host: Option<String>,
+    listener: Option<TcpListener>,
+}
+
+impl TcpConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes passive data connections from `ip`.
+    pub fn local_addr(mut self, ip: IpAddr) -> Self {
+        self.local_addr = Some(ip);
+        self
+    }
+
+    /// Makes passive data connections through `proxy`, active mode is
+    /// unsupported as the server can't connect back through it.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connects to the first of `target`'s addresses that accepts the
+    /// connection, returning the address as well.
+    ///
+    /// With a proxy a host name is sent to it unresolved, so the address
+    /// returned is unspecified and passive data connections to that address
+    /// are made to the host name instead.
+    pub(crate) fn connect_any(
+        &mut self,
+        target: &Target,
+        timeout: Option<Duration>,
+    ) -> io::Result<(TcpStream, SocketAddr)> {
+        match (&self.proxy, target) {
+            (Some(proxy), Target::Host(host, port)) => {
+                let stream = proxy::connect(proxy, target, timeout, self.local_addr)?;
+                self.host = Some(host.clone());
+
+                Ok((stream, SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), *port)))
+            }
+            (_, Target::Host(host, port)) => {
+                first_to_connect((host.as_str(), *port), |addr| self.connect(addr, timeout))
+            }
+            (_, Target::Addr(addr)) => Ok((self.connect(*addr, timeout)?, *addr)),
+        }
+    }
+}
+
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    fn connect(&mut self, addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let proxy = match &self.proxy {
+            Some(proxy) => proxy,
+            None => return connect_to(addr, timeout, self.local_addr),
+        };
+
+        let target = match &self.host {
+            Some(host) if addr.ip().is_unspecified() => Target::Host(host.clone(), addr.port()),
+            _ => Target::Addr(addr),
+        };
+
+        proxy::connect(proxy, &target, timeout, self.local_addr)
+    }
+
+    fn listen(&mut self, ip: IpAddr, ports: Option<RangeInclusive<u16>>) -> io::Result<SocketAddr> {
+        // We would tell the server to connect to our side of the connection
+        // to the proxy, which it can't reach
+        if self.proxy.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "active mode is not supported through a proxy",
+            ));
+        }
+
+        let listener = match ports {
+            Some(ports) => bind(ip, ports)?,
+            None => TcpListener::bind((ip, 0))?,
+        };
+
+        let addr = listener.local_addr()?;
+        self.listener = Some(listener);
+
+        Ok(addr)
+    }
+
+    fn accept(&mut self, peer: IpAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let listener = self.listener.take().ok_or_else(not_listening)?;
+
+        accept(listener, peer, timeout)
+    }
+}
+
+/// Connects to the first of `addr`'s addresses that accepts the connection,
+/// from `local` if there is one.
+pub(crate) fn connect(
+    addr: impl ToSocketAddrs,
+    timeout: Option<Duration>,
+    local: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+    let (stream, _) = first_to_connect(addr, |addr| connect_to(addr, timeout, local))?;
+
+    Ok(stream)
+}
+
+fn first_to_connect(
+    addr: impl ToSocketAddrs,
+    mut connect: impl FnMut(SocketAddr) -> io::Result<TcpStream>,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    let mut last_err = None;
+
+    for addr in addr.to_socket_addrs()? {
+        match connect(addr) {
+            Ok(stream) => return Ok((stream, addr)),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+    }))
+}
+
+fn connect_to(
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+    local: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if let Some(ip) = local {
+        socket.bind(&SocketAddr::new(ip, 0).into())?;
+    }
+
+    match timeout {
+        Some(timeout) => socket.connect_timeout(&addr.into(), timeout)?,
+        None => socket.connect(&addr.into())?,
+    }
+
+    Ok(socket.into())
+}
+
+fn bind(ip: IpAddr, ports: RangeInclusive<u16>) -> io::Result<TcpListener> {
+    let mut last_err = None;
+
+    for port in ports {
+        match TcpListener::bind((ip, port)) {
+            Ok(listener) => return Ok(listener),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty port range")))
+}
+
+/// Accepts a connection from `peer`, std has no way to time out doing so
+/// other than polling a non-blocking listener.
+fn accept(listener: TcpListener, peer: IpAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let deadline = match timeout {
+        Some(timeout) => Instant::now() + timeout,
+        None => loop {
+            let (stream, addr) = listener.accept()?;
+
+            if is_from(addr, peer) {
+                return Ok(stream);
+            }
+        },
+    };
+
+    listener.set_nonblocking(true)?;
+
+    loop {
+        match listener.accept() {
+            Ok((stream, addr)) if is_from(addr, peer) => {
+                // Some platforms have accepted sockets inherit the flag
+                stream.set_nonblocking(false)?;
+                return Ok(stream);
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "no data connection from the server",
+                    ));
+                }
+
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Whether a data connection from `addr` comes from the server, anyone else
+/// could be trying to steal or inject the transfer.
+fn is_from(addr: SocketAddr, peer: IpAddr) -> bool {
+    if addr.ip().to_canonical() == peer.to_canonical() {
+        return true;
+    }
+
+    warn!("Ignoring data connection from {}, expected {}", addr, peer);
+    false
+}
+
+fn not_listening() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "accepting without listening")
+}
+
+fn active_unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "active mode is not supported")
+}
+
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use super::{connect_to, Connector, TcpConnector};
+
+    const SERVER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_accept_from_peer() {
+        for timeout in [Some(Duration::from_secs(5)), None] {
+            let mut connector = TcpConnector::new();
+            let addr = connector.listen(SERVER, None).unwrap();
+
+            // Another host gets in before the server does
+            let _other = connect_to(addr, None, Some(IpAddr::from([127, 0, 0, 2]))).unwrap();
+            let server = connect_to(addr, None, Some(SERVER)).unwrap();
+
+            let stream = connector.accept(SERVER, timeout).unwrap();
+
+            assert_eq!(stream.peer_addr().unwrap(), server.local_addr().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_accept_without_listening() {
+        let err = TcpConnector::new().accept(SERVER, None).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+}
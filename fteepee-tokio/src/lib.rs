@@ -1,216 +1,543 @@
-use std::{error, fmt, marker::PhantomData, net::SocketAddr};
+use std::{
+    cmp::Ordering,
+    error, fmt,
+    future::Future,
+    io::{self, SeekFrom},
+    marker::PhantomData,
+    time::Duration,
+};
 
-use bytes::BytesMut;
 use fteepee_core::{
-    commands::{Command, Feat, List, Mlsd, Pass, Pasv, Stor, Type, User},
-    expect_code,
-    response::{ParsedResponseState, Response, ResponseExt},
-    Code, Config, Connected, Disconnected,
+    commands::redact,
+    response::Limits,
+    session::{Action, Output, Session},
+    Connected, DataMode, DirEntry, Disconnected,
 };
 use tokio::{
-    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, BufWriter},
-    net::{TcpStream, ToSocketAddrs},
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt,
+        AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+    },
+    net::TcpStream,
+    time::{self, Instant},
+};
+use tracing::{debug, enabled, trace};
+
+pub use fteepee_core::{
+    ErrorKind, FileError, Proxy, Reply, Target, TimeoutPhase, Timeouts, ToTarget,
 };
-use tracing::{enabled, trace};
 
-pub struct Client<State = Disconnected> {
-    stream: Lines<BufReader<TcpStream>>,
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls::ClientConfig;
+
+pub use crate::builder::ClientBuilder;
+pub use crate::transport::{Connector, TcpConnector, Transport};
+
+use crate::stream::{IdleTimeout, Stream};
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+
+mod builder;
+mod proxy;
+mod stream;
+#[cfg(feature = "tls")]
+mod tls;
+mod transport;
+
+/// An FTP client whose control connection is a `T` and whose data
+/// connections are opened by a `C`.
+pub struct Client<State = Disconnected, T = TcpStream, C = TcpConnector> {
+    stream: Stream<T>,
+    connector: C,
+    session: Session,
     read_buffer: Vec<u8>,
-    write_buffer: BytesMut,
     data_buffer: Vec<u8>,
-    response_buffer: Vec<u8>,
+    data_buffer_size: usize,
     marker: PhantomData<State>,
-    config: Config,
+    timeouts: Timeouts,
+    redact_logs: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
 }
 
 impl Client<Disconnected> {
-    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Client<Connected>> {
-        let stream = TcpStream::connect(addr).await?;
+    /// Sets up a client with anything other than the defaults.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
 
-        let mut client = Client {
-            stream: Lines::new(BufReader::new(stream)),
-            read_buffer: Vec::new(),
-            write_buffer: BytesMut::new(),
-            data_buffer: Vec::new(),
-            response_buffer: Vec::new(),
-            marker: PhantomData,
-            config: Config::default(),
-        };
+    pub async fn connect(addr: impl ToTarget) -> Result<Client<Connected>> {
+        ClientBuilder::new().connect(addr).await
+    }
 
-        let resp = client.read_response().await?;
-        expect_code!(resp.code()?, Code::READY);
+    /// Connects to a server using implicit FTPS, where TLS is negotiated as
+    /// soon as the connection is made, typically on port 990. All data
+    /// connections are protected as well.
+    ///
+    /// `domain` is the name the server's certificate is verified against.
+    #[cfg(feature = "tls")]
+    pub async fn connect_implicit_tls(
+        addr: impl ToTarget,
+        config: Arc<ClientConfig>,
+        domain: &str,
+    ) -> Result<Client<Connected>> {
+        ClientBuilder::new()
+            .implicit_tls(config, domain)
+            .connect(addr)
+            .await
+    }
+}
 
-        let cmd = Feat;
-        client.write_request(&cmd).await?;
-        let resp = client.read_response().await?;
-        expect_code!(
-            resp.code()?,
-            Code::SYSTEM_STATUS | Code::UNRECOGNIZED_COMMAND | Code::NOT_IMPLEMENTED
-        );
+impl<T: Transport, C: Connector> Client<Connected, T, C> {
+    /// Logs in, returning the server's final reply which often carries a
+    /// message of the day.
+    pub async fn login(&mut self, user: &str, pass: &str) -> Result<Reply> {
+        self.session.login(user, pass);
 
-        if matches!(resp.code()?, Code::SYSTEM_STATUS) {
-            let features = fteepee_core::parse_features(&client.response_buffer);
+        match self.execute().await? {
+            Output::Reply(reply) => Ok(reply),
+            output => unreachable!("login finished with {:?}", output),
+        }
+    }
 
-            if features.get("MLST").is_some() {
-                client.config.mlst_supported = true;
+    /// The server's greeting.
+    pub fn welcome(&self) -> &Reply {
+        self.session
+            .welcome()
+            .expect("connected without a greeting")
+    }
+
+    pub async fn list(&mut self, path: &str) -> Result<Vec<DirEntry>> {
+        self.session.list(path);
+        let stream = self.transfer().await?;
+
+        let mut lines = Lines::new(BufReader::with_capacity(self.data_buffer_size, stream));
+        let mut entries = Vec::new();
+
+        let result = loop {
+            let line = match lines.next(&mut self.data_buffer).await {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => break Err(data_timeout(err)),
+                None => break Ok(()),
+            };
+
+            match self.session.parse_entry(line) {
+                Ok(Some(entry)) => entries.push(entry),
+                Ok(None) => {}
+                Err(err) => debug!(
+                    "skipping listing line {:?}: {}",
+                    String::from_utf8_lossy(line),
+                    err
+                ),
             }
         };
 
-        Ok(client)
+        // We are done with this connection
+        drop(lines);
+        self.finish_transfer(result).await?;
+
+        Ok(entries)
     }
-}
 
-impl Client<Connected> {
-    pub async fn login(&mut self, user: &str, pass: &str) -> Result<()> {
-        let cmd = User::new(user);
+    pub async fn get<W: AsyncWrite + Unpin + ?Sized>(
+        &mut self,
+        path: &str,
+        writer: &mut W,
+    ) -> Result<()> {
+        self.get_from(path, 0, writer).await
+    }
 
-        self.write_request(&cmd).await?;
-        let resp = self.read_response().await?;
-        expect_code!(resp.code()?, Code::LOGGED_IN | Code::PASSWORD_REQUIRED);
+    /// Downloads `path` starting `offset` bytes in, only the remainder of the
+    /// file is written to `writer`.
+    pub async fn get_from<W: AsyncWrite + Unpin + ?Sized>(
+        &mut self,
+        path: &str,
+        offset: u64,
+        writer: &mut W,
+    ) -> Result<()> {
+        self.session.retrieve(path, offset);
+        let mut stream = BufReader::with_capacity(self.data_buffer_size, self.transfer().await?);
 
-        let cmd = Pass::new(pass);
+        let result = tokio::io::copy_buf(&mut stream, writer)
+            .await
+            .map_err(data_timeout);
 
-        self.write_request(&cmd).await?;
-        let resp = self.read_response().await?;
-        expect_code!(resp.code()?, Code::LOGGED_IN);
+        // We are done with this connection
+        drop(stream);
+        self.finish_transfer(result).await?;
 
         Ok(())
     }
 
-    pub async fn list(&mut self, path: &str) -> Result<()> {
-        let stream = if self.config.mlst_supported {
-            let cmd = Mlsd::new(path);
-            BufReader::new(self.data_connection(&cmd).await?)
-        } else {
-            let cmd = List::new(path);
-            BufReader::new(self.data_connection(&cmd).await?)
-        };
+    pub async fn put<R: AsyncRead + Unpin + ?Sized>(
+        &mut self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<()> {
+        self.put_from(path, 0, reader).await
+    }
+
+    /// Uploads `reader` to `path` starting `offset` bytes in, `reader` should
+    /// already be positioned at `offset`.
+    pub async fn put_from<R: AsyncRead + Unpin + ?Sized>(
+        &mut self,
+        path: &str,
+        offset: u64,
+        reader: &mut R,
+    ) -> Result<()> {
+        self.session.store(path, offset);
+        let mut stream = BufWriter::with_capacity(self.data_buffer_size, self.transfer().await?);
 
-        let resp = self.read_response().await?;
-        expect_code!(resp.code()?, Code::OPENING_DATA_CONNECTION);
+        let result = async {
+            tokio::io::copy(reader, &mut stream).await?;
 
-        let mut lines = Lines::new(stream);
+            // We are done with this connection
+            stream.shutdown().await?;
 
-        while let Some(line) = lines.next(&mut self.data_buffer).await {
-            let line = line?;
+            #[cfg(feature = "tls")]
+            stream
+                .get_mut()
+                .get_mut()
+                .read_ticket(self.timeouts.data_idle)
+                .await?;
 
-            dbg!(String::from_utf8_lossy(line));
+            Ok::<_, io::Error>(())
         }
+        .await
+        .map_err(data_timeout);
 
-        let resp = self.read_response().await?;
-        expect_code!(
-            resp.code()?,
-            Code::CLOSING_DATA_CONNECTION | Code::REQUESTED_FILE_ACTION_OKAY,
-        );
+        drop(stream);
+        self.finish_transfer(result).await?;
 
         Ok(())
     }
 
-    pub async fn put<R: AsyncRead + Unpin + ?Sized>(
+    /// Continues downloading `path` into `local`, only fetching the bytes that
+    /// are not already there.
+    pub async fn resume_download<W: AsyncWrite + AsyncSeek + Unpin + ?Sized>(
         &mut self,
         path: &str,
-        reader: &mut R,
+        local: &mut W,
     ) -> Result<()> {
-        let cmd = Type::Image;
+        let size = self.size(path).await?;
+        let offset = local.seek(SeekFrom::End(0)).await?;
+
+        match offset.cmp(&size) {
+            Ordering::Less => self.get_from(path, offset, local).await,
+            Ordering::Equal => Ok(()),
+            Ordering::Greater => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "local file is larger than the remote file",
+            )
+            .into()),
+        }
+    }
 
-        self.write_request(&cmd).await?;
-        let resp = self.read_response().await?;
-        expect_code!(resp.code()?, Code::COMMAND_OKAY);
+    /// Returns the size of `path` in bytes.
+    pub async fn size(&mut self, path: &str) -> Result<u64> {
+        self.session.size(path);
 
-        let cmd = Stor::new(path);
-        let mut stream = BufWriter::new(self.data_connection(&cmd).await?);
+        match self.execute().await? {
+            Output::Size(size) => Ok(size),
+            output => unreachable!("SIZE finished with {:?}", output),
+        }
+    }
 
-        let resp = self.read_response().await?;
-        expect_code!(
-            resp.code()?,
-            Code::DATA_CONNECTION_ALREADY_OPEN | Code::OPENING_DATA_CONNECTION
-        );
+    /// Changes the working directory to `path`.
+    pub async fn cwd(&mut self, path: &str) -> Result<()> {
+        self.session.cwd(path);
+        self.execute().await?;
 
-        tokio::io::copy(reader, &mut stream).await?;
+        Ok(())
+    }
 
-        // We are done with this connection
-        drop(stream);
+    /// Changes the working directory to its parent.
+    pub async fn cdup(&mut self) -> Result<()> {
+        self.session.cdup();
+        self.execute().await?;
 
-        let resp = self.read_response().await?;
-        expect_code!(
-            resp.code()?,
-            Code::CLOSING_DATA_CONNECTION | Code::REQUESTED_FILE_ACTION_OKAY,
-        );
+        Ok(())
+    }
+
+    /// Returns the working directory.
+    pub async fn pwd(&mut self) -> Result<String> {
+        self.session.pwd();
+
+        match self.execute().await? {
+            Output::Pathname(pathname) => Ok(pathname),
+            output => unreachable!("PWD finished with {:?}", output),
+        }
+    }
+
+    pub async fn mkdir(&mut self, path: &str) -> Result<()> {
+        self.session.mkdir(path);
+        self.execute().await?;
 
         Ok(())
     }
 
-    async fn data_connection<C: Command>(&mut self, cmd: &C) -> Result<TcpStream> {
-        let addr = self.pasv().await?;
+    pub async fn rmdir(&mut self, path: &str) -> Result<()> {
+        self.session.rmdir(path);
+        self.execute().await?;
 
-        self.write_request(cmd).await?;
+        Ok(())
+    }
 
-        let stream = TcpStream::connect(addr).await?;
+    pub async fn remove_file(&mut self, path: &str) -> Result<()> {
+        self.session.remove_file(path);
+        self.execute().await?;
 
-        Ok(stream)
+        Ok(())
     }
 
-    async fn pasv(&mut self) -> Result<SocketAddr> {
-        let cmd = Pasv;
+    pub async fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.session.rename(from, to);
+        self.execute().await?;
 
-        self.write_request(&cmd).await?;
-        let mut resp = self.read_response().await?;
-        expect_code!(resp.code()?, Code::ENTERING_PASSIVE_MODE);
+        Ok(())
+    }
 
-        Ok(SocketAddr::from(
-            resp.parse_passive_mode(&self.response_buffer)?,
-        ))
+    pub fn set_data_mode(&mut self, mode: DataMode) {
+        self.session.set_data_mode(mode);
     }
 
-    async fn read_response(&'_ mut self) -> Result<Response> {
-        self.read_buffer.clear();
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
 
-        let mut parsed_response = Response::new();
+    /// Bounds the size of replies, which fail with
+    /// [`fteepee_core::Error::ReplyTooLarge`] when they go over.
+    pub fn set_reply_limits(&mut self, limits: Limits) {
+        self.session.set_reply_limits(limits);
+    }
 
-        while let Some(line) = self.stream.next(&mut self.read_buffer).await {
-            let line = line?;
-            self.response_buffer
-                .resize(self.response_buffer.len() + line.len(), 0);
+    /// Reads the server's reply to a transfer once the data connection is
+    /// closed. The reply to a failed transfer is read too, so the next command
+    /// doesn't get it, but the transfer's error is what is returned.
+    async fn finish_transfer<R>(&mut self, result: Result<R>) -> Result<R> {
+        let output = match result {
+            Ok(output) => output,
+            Err(err) => {
+                self.session.transfer_failed();
 
-            let state = parsed_response.read_bytes(line, &mut self.response_buffer)?;
+                if let Err(reply_err) = self.execute().await {
+                    debug!("no reply to failed transfer: {}", reply_err);
+                }
 
-            if matches!(state, ParsedResponseState::Complete) {
-                break;
+                return Err(err);
             }
-        }
+        };
 
-        if enabled!(tracing::Level::TRACE) {
-            trace!(
-                "<-- {}",
-                String::from_utf8_lossy(parsed_response.message(&self.response_buffer))
-            );
-        }
+        self.session.transfer_complete();
+        self.execute().await?;
 
-        Ok(parsed_response)
+        Ok(output)
     }
+}
 
-    async fn write_request<C: Command>(&mut self, cmd: &C) -> Result<()> {
-        self.write_buffer.clear();
+#[cfg(feature = "tls")]
+impl<T: Transport, C: Connector> Client<Connected, T, C> {
+    /// Upgrades the control connection to TLS using AUTH TLS and protects all
+    /// subsequent data connections, this should be done before logging in.
+    /// Data connections resume the control connection's TLS session.
+    ///
+    /// `domain` is the name the server's certificate is verified against.
+    pub async fn secure(self, config: Arc<ClientConfig>, domain: &str) -> Result<Self> {
+        let tls = TlsConfig::new(config, domain)?;
+
+        self.upgrade(tls).await
+    }
 
-        self.write_buffer.resize(cmd.size(), 0);
+    async fn upgrade(mut self, tls: TlsConfig) -> Result<Self> {
+        self.session.auth_tls();
+        self.execute().await?;
 
-        cmd.encode(&mut self.write_buffer);
+        // The handshake is bounded like a reply
+        let stream = timeout(
+            self.timeouts.reply,
+            TimeoutPhase::Reply,
+            self.stream.into_tls(&tls.control, &tls.server_name),
+        )
+        .await?;
 
-        self.stream
-            .reader
-            .get_mut()
-            .write_all(&self.write_buffer[..cmd.size()])
-            .await?;
+        let mut client = Client {
+            stream,
+            tls: Some(tls),
+            ..self
+        };
 
-        if enabled!(tracing::Level::TRACE) {
-            trace!(
-                "--> {}",
-                String::from_utf8_lossy(&self.write_buffer[..cmd.size()])
-            );
+        client.session.protect_data();
+        client.execute().await?;
+
+        Ok(client)
+    }
+}
+
+impl<State, T: Transport, C: Connector> Client<State, T, C> {
+    /// Drives the session until the current operation finishes or its data
+    /// connection is ready for the transfer.
+    async fn run(&mut self) -> Result<Progress<C::Stream>> {
+        let mut data = None;
+        // When the reply being waited for is due
+        let mut deadline = None;
+
+        loop {
+            match self.session.poll()? {
+                Action::Send(bytes) => {
+                    self.stream.write_all(bytes).await?;
+                    deadline = None;
+
+                    if enabled!(tracing::Level::TRACE) {
+                        let bytes = if self.redact_logs {
+                            redact(bytes)
+                        } else {
+                            bytes.into()
+                        };
+
+                        trace!("--> {}", String::from_utf8_lossy(&bytes));
+                    }
+                }
+                Action::Receive => {
+                    let read = self.stream.read(&mut self.read_buffer);
+
+                    let n = match self.timeouts.reply {
+                        Some(timeout) => {
+                            let deadline =
+                                *deadline.get_or_insert_with(|| Instant::now() + timeout);
+
+                            time::timeout_at(deadline, read)
+                                .await
+                                .map_err(|_| Error::Timeout(TimeoutPhase::Reply))??
+                        }
+                        None => read.await?,
+                    };
+
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "control connection closed",
+                        )
+                        .into());
+                    }
+
+                    if enabled!(tracing::Level::TRACE) {
+                        trace!("<-- {}", String::from_utf8_lossy(&self.read_buffer[..n]));
+                    }
+
+                    self.session.receive(&self.read_buffer[..n]);
+                }
+                Action::Connect(addr) => {
+                    let stream = timeout(
+                        self.timeouts.connect,
+                        TimeoutPhase::Connect,
+                        self.connector.connect(addr),
+                    )
+                    .await?;
+
+                    data = Some(self.data_stream(stream).await?);
+                    self.session.data_connected();
+                }
+                Action::Listen(ports) => {
+                    let ip = self.stream.get_ref().local_addr()?.ip();
+                    let addr = self.connector.listen(ip, ports).await?;
+
+                    self.session.listening(addr);
+                }
+                Action::Accept => {
+                    let peer = self.stream.get_ref().peer_addr()?.ip();
+                    let stream = timeout(
+                        self.timeouts.data_idle,
+                        TimeoutPhase::Data,
+                        self.connector.accept(peer),
+                    )
+                    .await?;
+
+                    data = Some(self.data_stream(stream).await?);
+                    self.session.data_connected();
+                }
+                Action::Transfer => {
+                    let stream = data.take().expect("transferring without a connection");
+
+                    return Ok(Progress::Transfer(stream));
+                }
+                Action::Done(output) => return Ok(Progress::Done(output)),
+            }
         }
+    }
 
-        Ok(())
+    /// Runs an operation that does not transfer any data.
+    async fn execute(&mut self) -> Result<Output> {
+        match self.run().await? {
+            Progress::Done(output) => Ok(output),
+            Progress::Transfer(_) => unreachable!("operation opened a data connection"),
+        }
     }
+
+    /// Runs a transfer operation up to the point the data can be sent.
+    async fn transfer(&mut self) -> Result<IdleTimeout<Stream<C::Stream>>> {
+        match self.run().await? {
+            Progress::Transfer(stream) => Ok(IdleTimeout::new(stream, self.timeouts.data_idle)),
+            Progress::Done(_) => unreachable!("transfer finished without a data connection"),
+        }
+    }
+
+    async fn data_stream(&self, stream: C::Stream) -> Result<Stream<C::Stream>> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return timeout(
+                self.timeouts.data_idle,
+                TimeoutPhase::Data,
+                Stream::Plain(stream).into_tls(&tls.data, &tls.server_name),
+            )
+            .await;
+        }
+
+        Ok(Stream::Plain(stream))
+    }
+}
+
+/// Runs `future`, giving up after `duration` if there is one.
+async fn timeout<T, E>(
+    duration: Option<Duration>,
+    phase: TimeoutPhase,
+    future: impl Future<Output = std::result::Result<T, E>>,
+) -> Result<T>
+where
+    Error: From<E>,
+{
+    let result = match duration {
+        Some(duration) => time::timeout(duration, future)
+            .await
+            .map_err(|_| Error::Timeout(phase))?,
+        None => future.await,
+    };
+
+    Ok(result?)
+}
+
+/// Reports a data connection that went idle for too long as a timeout. Our
+/// own errors passed back by a connector keep their kind and are not timeouts.
+fn data_timeout(err: impl Into<Error>) -> Error {
+    match err.into() {
+        Error::IO(err)
+            if err.kind() == io::ErrorKind::TimedOut
+                && !err.get_ref().is_some_and(|err| err.is::<Error>()) =>
+        {
+            Error::Timeout(TimeoutPhase::Data)
+        }
+        err => err,
+    }
+}
+
+const READ_BUFFER_SIZE: usize = 4096;
+const DATA_BUFFER_SIZE: usize = 8 * 1024;
+
+enum Progress<S> {
+    Transfer(Stream<S>),
+    Done(Output),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -219,6 +546,16 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     IO(std::io::Error),
     Internal(fteepee_core::Error),
+    /// The server refused to act on `path`.
+    File {
+        kind: FileError,
+        path: String,
+        message: String,
+    },
+    /// Gave up waiting, see [`Timeouts`].
+    Timeout(TimeoutPhase),
+    #[cfg(feature = "tls")]
+    Tls(tokio_rustls::rustls::Error),
 }
 
 impl fmt::Display for Error {
@@ -226,6 +563,14 @@ impl fmt::Display for Error {
         match self {
             Error::IO(err) => err.fmt(f),
             Error::Internal(err) => err.fmt(f),
+            Error::File {
+                kind,
+                path,
+                message,
+            } => write!(f, "{:?} {}: {}", path, kind, message),
+            Error::Timeout(phase) => write!(f, "timed out {}", phase),
+            #[cfg(feature = "tls")]
+            Error::Tls(err) => err.fmt(f),
         }
     }
 }
@@ -235,6 +580,43 @@ impl error::Error for Error {
         match self {
             Error::IO(err) => Some(err),
             Error::Internal(err) => Some(err),
+            Error::File { .. } | Error::Timeout(_) => None,
+            #[cfg(feature = "tls")]
+            Error::Tls(err) => Some(err),
+        }
+    }
+}
+
+impl Error {
+    /// Categorizes the error, e.g. to decide whether to retry.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            // Connectors can only fail with I/O errors
+            Error::IO(err) => match err.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+                Some(err) => err.kind(),
+                None => err.kind().into(),
+            },
+            Error::Internal(err) => err.kind(),
+            Error::File { kind, message, .. } => ErrorKind::from_file_error(*kind, message),
+            Error::Timeout(_) => ErrorKind::Transient,
+            #[cfg(feature = "tls")]
+            Error::Tls(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Whether the operation could succeed if it is tried again, possibly on
+    /// a new connection.
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::IO(err) => err,
+            err @ Error::Timeout(_) => io::Error::new(io::ErrorKind::TimedOut, err),
+            err => io::Error::new(err.kind().into(), err),
         }
     }
 }
@@ -247,7 +629,25 @@ impl From<std::io::Error> for Error {
 
 impl From<fteepee_core::Error> for Error {
     fn from(err: fteepee_core::Error) -> Self {
-        Self::Internal(err)
+        match err {
+            fteepee_core::Error::File {
+                kind,
+                path,
+                message,
+            } => Self::File {
+                kind,
+                path,
+                message,
+            },
+            err => Self::Internal(err),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<tokio_rustls::rustls::Error> for Error {
+    fn from(err: tokio_rustls::rustls::Error) -> Self {
+        Self::Tls(err)
     }
 }
 
@@ -280,9 +680,230 @@ impl<B: AsyncBufRead + Unpin> Lines<B> {
 
 #[cfg(test)]
 mod tests {
+    use std::{io, net::SocketAddr, time::Duration};
+
+    use tokio::{
+        io::{duplex, sink, AsyncReadExt, AsyncWriteExt, DuplexStream},
+        net::TcpListener,
+    };
+
+    use super::{Client, Connector, DataMode, Error, TimeoutPhase, Transport};
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    impl Transport for DuplexStream {
+        fn peer_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([127, 0, 0, 1], 21)))
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([127, 0, 0, 1], 50000)))
+        }
+    }
+
+    /// Returns a pipe the server has already written `script` to, and the
+    /// server's end to read what the client sends.
+    async fn scripted(script: &str) -> (DuplexStream, DuplexStream) {
+        let (client, mut server) = duplex(4096);
+        server.write_all(script.as_bytes()).await.unwrap();
+
+        (client, server)
+    }
+
+    /// Opens every data connection to the same script.
+    struct Scripted(&'static str);
+
+    impl Connector for Scripted {
+        type Stream = DuplexStream;
+
+        async fn connect(&mut self, addr: SocketAddr) -> io::Result<DuplexStream> {
+            assert_eq!(addr, SocketAddr::from(([127, 0, 0, 1], 1025)));
+
+            // The server closes the connection once it is done
+            Ok(scripted(self.0).await.0)
+        }
+    }
+
+    /// Writes `script` to whoever connects and then goes quiet until they
+    /// hang up, timeouts need a real socket.
+    async fn stall(script: String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(script.as_bytes()).await.unwrap();
+            let _ = tokio::io::copy(&mut stream, &mut sink()).await;
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_connect_with() {
+        let (control, mut server) = scripted(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "331 Password required\r\n",
+            "230 Logged in\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "226 Done\r\n",
+        ))
+        .await;
+        let connector = Scripted("-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n");
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, connector)
+            .await
+            .unwrap();
+
+        client.login("user", "pass").await.unwrap();
+        let entries = client.list("/").await.unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a.txt");
+
+        drop(client);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+
+        assert_eq!(sent, "SYST\r\nUSER user\r\nPASS pass\r\nPASV\r\nLIST /\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let (control, mut server) = scripted(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "200 Type set to I\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "226 Done\r\n",
+        ))
+        .await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, Scripted("hello world"))
+            .await
+            .unwrap();
+
+        let mut file = Vec::new();
+        client.get("a.txt", &mut file).await.unwrap();
+
+        assert_eq!(file, b"hello world");
+
+        drop(client);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+
+        assert_eq!(sent, "SYST\r\nTYPE I\r\nPASV\r\nRETR a.txt\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_failed_get() {
+        let (control, mut server) = scripted(concat!(
+            "220 Ready\r\n",
+            "215 UNIX Type: L8\r\n",
+            "200 Type set to I\r\n",
+            "227 Entering Passive Mode (127,0,0,1,4,1)\r\n",
+            "150 Opening\r\n",
+            "426 Connection closed; transfer aborted\r\n",
+            "257 \"/\" is the current directory\r\n",
+        ))
+        .await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .connect_with(control, Scripted("hello world"))
+            .await
+            .unwrap();
+
+        // Nobody is reading, so writing the file fails part way
+        let (mut file, reader) = duplex(1);
+        drop(reader);
+        let err = client.get("a.txt", &mut file).await.unwrap_err();
+
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::BrokenPipe);
+        // The reply to the transfer is not mistaken for the one to PWD
+        assert_eq!(client.pwd().await.unwrap(), "/");
+
+        drop(client);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+
+        assert_eq!(sent, "SYST\r\nTYPE I\r\nPASV\r\nRETR a.txt\r\nPWD\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_reply_timeout() {
+        let addr = stall("220 Ready\r\n215 UNIX Type: L8\r\n".into()).await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .reply_timeout(Duration::from_millis(50))
+            .connect(addr)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            client.pwd().await,
+            Err(Error::Timeout(TimeoutPhase::Reply))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_data_timeout() {
+        let data = stall(String::new()).await;
+        let addr = stall(format!(
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "200 Type set to I\r\n",
+                "227 Entering Passive Mode (127,0,0,1,{},{})\r\n",
+                "150 Opening\r\n",
+                "426 Connection closed; transfer aborted\r\n",
+            ),
+            data.port() >> 8,
+            data.port() & 0xff
+        ))
+        .await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_idle_timeout(Duration::from_millis(50))
+            .connect(addr)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            client.get("a.txt", &mut Vec::new()).await,
+            Err(Error::Timeout(TimeoutPhase::Data))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_active_unsupported() {
+        let (control, _server) = scripted("220 Ready\r\n215 UNIX Type: L8\r\n").await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_mode(DataMode::Active {
+                external_ip: None,
+                ports: None,
+            })
+            .connect_with(control, Scripted(""))
+            .await
+            .unwrap();
+
+        let err = client.list("/").await.unwrap_err();
+
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+    }
 }
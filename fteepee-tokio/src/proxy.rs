@@ -0,0 +1,375 @@
+use std::{io, net::IpAddr};
+
+use fteepee_core::{
+    proxy::{http, socks5, ProxyKind},
+    ProxyError,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{transport, Proxy, Result, Target};
+
+/// Connects to `target` through `proxy`.
+pub(crate) async fn connect(
+    proxy: &Proxy,
+    target: &Target,
+    local: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+    let mut stream = transport::connect(proxy.addr.as_str(), local).await?;
+
+    match proxy.kind {
+        ProxyKind::Socks5 => connect_socks5(&mut stream, proxy, target).await?,
+        ProxyKind::Http => connect_http(&mut stream, proxy, target).await?,
+    }
+
+    Ok(stream)
+}
+
+async fn connect_socks5(stream: &mut TcpStream, proxy: &Proxy, target: &Target) -> Result<()> {
+    let credentials = proxy.credentials.as_ref();
+
+    stream.write_all(&socks5::greeting(credentials)).await?;
+
+    if socks5::parse_method(read_array(stream).await?)? {
+        // Only offered with credentials
+        let credentials =
+            credentials.ok_or(fteepee_core::Error::Proxy(ProxyError::InvalidReply))?;
+
+        stream
+            .write_all(&socks5::auth_request(credentials)?)
+            .await?;
+        socks5::parse_auth(read_array(stream).await?)?;
+    }
+
+    stream.write_all(&socks5::connect_request(target)?).await?;
+    let remaining = socks5::parse_connect(read_array(stream).await?)?;
+
+    // The address the proxy connected from is of no use
+    stream.read_exact(&mut vec![0; remaining]).await?;
+
+    Ok(())
+}
+
+async fn connect_http(stream: &mut TcpStream, proxy: &Proxy, target: &Target) -> Result<()> {
+    stream
+        .write_all(&http::connect_request(target, proxy.credentials.as_ref()))
+        .await?;
+
+    let mut response = Vec::new();
+
+    // A byte at a time so that none of the server's greeting is read
+    while !http::is_complete(&response) {
+        if response.len() == http::MAX_RESPONSE_SIZE {
+            return Err(fteepee_core::Error::Proxy(ProxyError::InvalidReply).into());
+        }
+
+        response.push(stream.read_u8().await?);
+    }
+
+    http::parse_response(&response)?;
+
+    Ok(())
+}
+
+async fn read_array<const N: usize>(stream: &mut TcpStream) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    stream.read_exact(&mut buf).await?;
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::SocketAddr};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    use fteepee_core::DataMode;
+
+    use crate::{Client, Proxy};
+
+    /// What the stand-in expects a connection to be opened to, for SOCKS5
+    /// starting with the address type.
+    enum Handshake {
+        Socks5(&'static [u8]),
+        Http(&'static str),
+    }
+
+    /// Stands in for a proxy, and for the server behind it once each
+    /// connection's handshake is done. Returns what the client sent on the
+    /// control connection.
+    async fn stand_in(
+        handshakes: Vec<Handshake>,
+        replies: &'static str,
+        listing: &'static str,
+    ) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let mut handshakes = handshakes.into_iter();
+
+            let (mut control, _) = listener.accept().await.unwrap();
+            handshake(&mut control, handshakes.next().unwrap()).await;
+            control.write_all(replies.as_bytes()).await.unwrap();
+
+            for target in handshakes {
+                let (mut data, _) = listener.accept().await.unwrap();
+                handshake(&mut data, target).await;
+                data.write_all(listing.as_bytes()).await.unwrap();
+            }
+
+            let mut sent = String::new();
+            control.read_to_string(&mut sent).await.unwrap();
+            sent
+        });
+
+        (addr, handle)
+    }
+
+    async fn expect(stream: &mut TcpStream, expected: &[u8]) {
+        let mut buf = vec![0; expected.len()];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(buf, expected);
+    }
+
+    async fn handshake(stream: &mut TcpStream, handshake: Handshake) {
+        match handshake {
+            Handshake::Socks5(target) => {
+                expect(stream, &[5, 2, 0, 2]).await;
+                stream.write_all(&[5, 2]).await.unwrap();
+                expect(stream, b"\x01\x04user\x04pass").await;
+                stream.write_all(&[1, 0]).await.unwrap();
+                expect(stream, &[[5, 1, 0].as_slice(), target].concat()).await;
+                stream
+                    .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 4, 1])
+                    .await
+                    .unwrap();
+            }
+            Handshake::Http(target) => {
+                let request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n");
+                expect(stream, request.as_bytes()).await;
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socks5() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                Handshake::Socks5(&[1, 192, 0, 2, 1, 0, 21]),
+                // EPSV connects to the same host as the control connection
+                Handshake::Socks5(&[1, 192, 0, 2, 1, 4, 1]),
+                Handshake::Socks5(&[1, 192, 0, 2, 1, 4, 2]),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "211-Features:\r\n EPSV\r\n211 End\r\n",
+                "215 UNIX Type: L8\r\n",
+                "229 Entering Extended Passive Mode (|||1025|)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+                "500 EPSV disabled\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        )
+        .await;
+
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .proxy(proxy)
+            .connect("192.0.2.1:21")
+            .await
+            .unwrap();
+
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+        // Falls back to PASV, which only takes the port from the reply
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(
+            stand_in.await.unwrap(),
+            "FEAT\r\nSYST\r\nEPSV\r\nLIST /\r\nEPSV\r\nPASV\r\nLIST /\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_socks5_host() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                Handshake::Socks5(b"\x03\x0bftp.invalid\x00\x15"),
+                Handshake::Socks5(b"\x03\x0bftp.invalid\x04\x01"),
+                Handshake::Socks5(b"\x03\x0bftp.invalid\x04\x02"),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "211-Features:\r\n EPSV\r\n211 End\r\n",
+                "215 UNIX Type: L8\r\n",
+                "229 Entering Extended Passive Mode (|||1025|)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+                "500 EPSV disabled\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        )
+        .await;
+
+        // Can't be resolved locally, only the proxy is given the name
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .proxy(proxy)
+            .connect("ftp.invalid:21")
+            .await
+            .unwrap();
+
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(
+            stand_in.await.unwrap(),
+            "FEAT\r\nSYST\r\nEPSV\r\nLIST /\r\nEPSV\r\nPASV\r\nLIST /\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                Handshake::Http("192.0.2.1:21"),
+                Handshake::Http("192.0.2.1:1026"),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        )
+        .await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .proxy(Proxy::http(addr.to_string()))
+            .connect("192.0.2.1:21")
+            .await
+            .unwrap();
+
+        // The greeting is not mistaken for part of the proxy's response
+        assert_eq!(client.welcome().message(), "Ready");
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(stand_in.await.unwrap(), "SYST\r\nPASV\r\nLIST /\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_http_host() {
+        let (addr, stand_in) = stand_in(
+            vec![
+                Handshake::Http("ftp.invalid:21"),
+                Handshake::Http("ftp.invalid:1026"),
+            ],
+            concat!(
+                "220 Ready\r\n",
+                "215 UNIX Type: L8\r\n",
+                "227 Entering Passive Mode (192,0,2,2,4,2)\r\n",
+                "150 Opening\r\n",
+                "226 Done\r\n",
+            ),
+            "-rw-r--r-- 1 ftp ftp 3 Jan 1 2020 a.txt\r\n",
+        )
+        .await;
+
+        let mut client = Client::builder()
+            .probe_features(false)
+            .proxy(Proxy::http(addr.to_string()))
+            .connect(("ftp.invalid", 21))
+            .await
+            .unwrap();
+
+        assert_eq!(client.list("/").await.unwrap()[0].name, "a.txt");
+
+        drop(client);
+        assert_eq!(stand_in.await.unwrap(), "SYST\r\nPASV\r\nLIST /\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_active() {
+        let (addr, stand_in) = stand_in(
+            vec![Handshake::Socks5(&[1, 192, 0, 2, 1, 0, 21])],
+            "220 Ready\r\n215 UNIX Type: L8\r\n",
+            "",
+        )
+        .await;
+
+        let proxy = Proxy::socks5(addr.to_string()).credentials("user", "pass");
+        let mut client = Client::builder()
+            .probe_features(false)
+            .data_mode(DataMode::Active {
+                external_ip: None,
+                ports: None,
+            })
+            .proxy(proxy)
+            .connect("192.0.2.1:21")
+            .await
+            .unwrap();
+
+        // The server can't connect back through the proxy
+        let err = client.list("/").await.unwrap_err();
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+
+        drop(client);
+        assert_eq!(stand_in.await.unwrap(), "SYST\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stand_in = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            expect(&mut stream, &[5, 1, 0]).await;
+            stream.write_all(&[5, 0]).await.unwrap();
+            expect(&mut stream, &[5, 1, 0, 1, 192, 0, 2, 1, 0, 21]).await;
+            stream
+                .write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        });
+
+        let err = Client::builder()
+            .proxy(Proxy::socks5(addr.to_string()))
+            .connect("192.0.2.1:21")
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.to_string(),
+            "SOCKS5 proxy failed to connect: connection refused"
+        );
+        assert!(err.is_retryable());
+        stand_in.await.unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+#[cfg(feature = "tls")]
+use tokio::io::AsyncReadExt;
+#[cfg(feature = "tls")]
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{pki_types::ServerName, ClientConfig, ProtocolVersion},
+    TlsConnector,
+};
+
+use crate::Transport;
+
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+/// A control or data connection, optionally protected by TLS.
+pub(crate) enum Stream<T> {
+    Plain(T),
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream<T>>),
+}
+
+impl<T: Transport> Stream<T> {
+    pub(crate) fn get_ref(&self) -> &T {
+        match self {
+            Stream::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+
+    /// Reads from a TLS 1.3 connection until the server's session ticket
+    /// arrives or it closes, giving up quietly once `timeout` passes. Tickets
+    /// come after the handshake and are only seen when reading, which uploads
+    /// don't otherwise do, later data connections need one to resume the
+    /// session.
+    #[cfg(feature = "tls")]
+    pub(crate) async fn read_ticket(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Plain(_) => Ok(()),
+            Stream::Tls(stream) => match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, read_ticket(stream))
+                    .await
+                    .unwrap_or(Ok(())),
+                None => read_ticket(stream).await,
+            },
+        }
+    }
+
+    /// Performs a TLS handshake over a plain connection.
+    #[cfg(feature = "tls")]
+    pub(crate) async fn into_tls(
+        self,
+        config: &Arc<ClientConfig>,
+        server_name: &ServerName<'static>,
+    ) -> crate::Result<Self> {
+        let stream = match self {
+            Stream::Plain(stream) => stream,
+            Stream::Tls(_) => return Ok(self),
+        };
+
+        let stream = TlsConnector::from(Arc::clone(config))
+            .connect(server_name.clone(), stream)
+            .await?;
+
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+}
+
+/// Feeds the connection what the server sends until there is a ticket, going
+/// around the stream's reads which only return once there is data.
+#[cfg(feature = "tls")]
+async fn read_ticket<T: Transport>(stream: &mut TlsStream<T>) -> io::Result<()> {
+    let (sock, conn) = stream.get_mut();
+    let mut buf = [0; 4096];
+
+    while conn.protocol_version() == Some(ProtocolVersion::TLSv1_3)
+        && conn.tls13_tickets_received() == 0
+    {
+        let n = sock.read(&mut buf).await?;
+        let mut received = &buf[..n];
+
+        if received.is_empty() {
+            break;
+        }
+
+        while !received.is_empty() {
+            conn.read_tls(&mut received)?;
+
+            let state = conn
+                .process_new_packets()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            if state.peer_has_closed() {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<T: Transport> AsyncRead for Stream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<T: Transport> AsyncWrite for Stream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Fails reads and writes with [`io::ErrorKind::TimedOut`] once the inner
+/// stream has not made progress for `timeout`.
+pub(crate) struct IdleTimeout<S> {
+    inner: S,
+    timeout: Option<Duration>,
+    /// Started when the inner stream first has to wait.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> IdleTimeout<S> {
+    pub(crate) fn new(inner: S, timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            timeout,
+            sleep: None,
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    pub(crate) fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    fn poll_idle<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.sleep = None;
+            return poll;
+        }
+
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        ready!(sleep.as_mut().poll(cx));
+        self.sleep = None;
+
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "data connection idle",
+        )))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        this.poll_idle(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        this.poll_idle(cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_flush(cx);
+
+        this.poll_idle(cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_shutdown(cx);
+
+        this.poll_idle(cx, poll)
+    }
+}
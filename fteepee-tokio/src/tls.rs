@@ -0,0 +1,413 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use tokio_rustls::rustls::{
+    client::{ClientSessionStore, Resumption, Tls12ClientSessionValue, Tls13ClientSessionValue},
+    pki_types::ServerName,
+    ClientConfig, NamedGroup,
+};
+
+/// The most TLS 1.3 tickets kept at once for each kind of connection, older
+/// tickets are dropped first.
+const MAX_TLS13_TICKETS: usize = 8;
+
+/// TLS settings shared by the control and data connections.
+pub(crate) struct TlsConfig {
+    pub(crate) control: Arc<ClientConfig>,
+    pub(crate) data: Arc<ClientConfig>,
+    pub(crate) server_name: ServerName<'static>,
+}
+
+impl TlsConfig {
+    /// Many servers reject data connections that do not resume the control
+    /// connection's session, so every client gets a session store of its own
+    /// regardless of how resumption was configured.
+    pub(crate) fn new(config: Arc<ClientConfig>, domain: &str) -> crate::Result<Self> {
+        let server_name = ServerName::try_from(domain.to_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let sessions = Arc::new(Sessions::default());
+        let with_store = |control| {
+            let mut config = ClientConfig::clone(&config);
+            config.resumption = Resumption::store(Arc::new(SessionStore {
+                sessions: Arc::clone(&sessions),
+                control,
+            }));
+
+            Arc::new(config)
+        };
+
+        Ok(TlsConfig {
+            control: with_store(true),
+            data: with_store(false),
+            server_name,
+        })
+    }
+}
+
+/// Sessions negotiated by a single client. A client only ever talks to one
+/// server so the server name is ignored.
+#[derive(Debug, Default)]
+struct Sessions {
+    kx_hint: Mutex<Option<NamedGroup>>,
+    tls12: Mutex<Option<Tls12ClientSessionValue>>,
+    control_tickets: Mutex<VecDeque<Tls13ClientSessionValue>>,
+    data_tickets: Mutex<VecDeque<Tls13ClientSessionValue>>,
+}
+
+/// The client's [`Sessions`] as seen by either its control connection or its
+/// data connections, so tickets can be told apart by where they came from.
+#[derive(Debug)]
+struct SessionStore {
+    sessions: Arc<Sessions>,
+    control: bool,
+}
+
+impl ClientSessionStore for SessionStore {
+    fn set_kx_hint(&self, _: ServerName<'static>, group: NamedGroup) {
+        *lock(&self.sessions.kx_hint) = Some(group);
+    }
+
+    fn kx_hint(&self, _: &ServerName<'_>) -> Option<NamedGroup> {
+        *lock(&self.sessions.kx_hint)
+    }
+
+    fn set_tls12_session(&self, _: ServerName<'static>, value: Tls12ClientSessionValue) {
+        // Data connections resume the control connection's session, so a new
+        // session only shows up when the server could not resume the last
+        lock(&self.sessions.tls12).replace(value);
+    }
+
+    fn tls12_session(&self, _: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        lock(&self.sessions.tls12).clone()
+    }
+
+    fn remove_tls12_session(&self, _: &ServerName<'static>) {
+        lock(&self.sessions.tls12).take();
+    }
+
+    fn insert_tls13_ticket(&self, _: ServerName<'static>, value: Tls13ClientSessionValue) {
+        let mut tickets = if self.control {
+            lock(&self.sessions.control_tickets)
+        } else {
+            lock(&self.sessions.data_tickets)
+        };
+
+        if tickets.len() == MAX_TLS13_TICKETS {
+            tickets.pop_front();
+        }
+
+        tickets.push_back(value);
+    }
+
+    fn take_tls13_ticket(&self, _: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        // Servers such as FileZilla expect the control connection's session,
+        // tickets from data connections are only used once those run out
+        let ticket = lock(&self.sessions.control_tickets).pop_back();
+
+        ticket.or_else(|| lock(&self.sessions.data_tickets).pop_back())
+    }
+}
+
+/// The store only holds plain values so a panic elsewhere cannot leave it in
+/// an inconsistent state.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::Arc,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use tokio_rustls::rustls::{
+        crypto::ring::default_provider,
+        pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+        version::{TLS12, TLS13},
+        ClientConfig, HandshakeKind, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+        SupportedProtocolVersion,
+    };
+
+    use crate::Client;
+
+    /// What the stand-in saw of the client.
+    struct Seen {
+        commands: Vec<String>,
+        /// Whether each data connection resumed a TLS session.
+        resumed: Vec<bool>,
+    }
+
+    trait Connection: Read + Write + Send {}
+
+    impl<T: Read + Write + Send> Connection for T {}
+
+    /// Configs for a server with a fresh certificate for `localhost`, and for
+    /// a client that trusts it, both limited to `version`.
+    fn configs(
+        version: &'static SupportedProtocolVersion,
+    ) -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+        let certified = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+        let cert = certified.cert.der().clone();
+        let key = PrivatePkcs8KeyDer::from(certified.signing_key.serialize_der());
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.clone()).unwrap();
+
+        let server = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_protocol_versions(&[version])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], PrivateKeyDer::Pkcs8(key))
+            .unwrap();
+        let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_protocol_versions(&[version])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        (Arc::new(server), Arc::new(client))
+    }
+
+    fn accept_tls(
+        config: &Arc<ServerConfig>,
+        mut stream: TcpStream,
+    ) -> StreamOwned<ServerConnection, TcpStream> {
+        let mut conn = ServerConnection::new(Arc::clone(config)).unwrap();
+
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream).unwrap();
+        }
+
+        StreamOwned::new(conn, stream)
+    }
+
+    fn read_line(stream: &mut dyn Connection) -> Option<String> {
+        let mut line = Vec::new();
+        let mut byte = [0];
+
+        while !line.ends_with(b"\r\n") {
+            match stream.read(&mut byte) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => line.push(byte[0]),
+            }
+        }
+
+        line.truncate(line.len() - 2);
+        Some(String::from_utf8(line).unwrap())
+    }
+
+    /// Plays an FTPS server that sends `file` for every RETR, protecting the
+    /// control connection from the start when `implicit`.
+    fn stand_in(
+        config: Arc<ServerConfig>,
+        implicit: bool,
+        file: &'static [u8],
+    ) -> (SocketAddr, JoinHandle<Seen>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let data = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let plain = stream.try_clone().unwrap();
+            let mut control: Box<dyn Connection> = match implicit {
+                true => Box::new(accept_tls(&config, stream)),
+                false => Box::new(stream),
+            };
+            let mut seen = Seen {
+                commands: Vec::new(),
+                resumed: Vec::new(),
+            };
+
+            let mut held = None;
+
+            control.write_all(b"220 Ready\r\n").unwrap();
+
+            while let Some(command) = read_line(control.as_mut()) {
+                let reply = match command.split(' ').next().unwrap() {
+                    "AUTH" => {
+                        control.write_all(b"234 Proceed\r\n").unwrap();
+                        control = Box::new(accept_tls(&config, plain.try_clone().unwrap()));
+                        seen.commands.push(command);
+                        continue;
+                    }
+                    "SYST" => "215 UNIX Type: L8".to_owned(),
+                    "PASV" => {
+                        let port = data.local_addr().unwrap().port();
+                        format!(
+                            "227 Entering Passive Mode (127,0,0,1,{},{})",
+                            port >> 8,
+                            port & 0xff
+                        )
+                    }
+                    "RETR" => {
+                        control.write_all(b"150 Opening\r\n").unwrap();
+
+                        let (stream, _) = data.accept().unwrap();
+                        let mut stream = accept_tls(&config, stream);
+                        seen.resumed
+                            .push(stream.conn.handshake_kind() == Some(HandshakeKind::Resumed));
+                        stream.write_all(file).unwrap();
+                        stream.conn.send_close_notify();
+                        stream.flush().unwrap();
+
+                        "226 Done".to_owned()
+                    }
+                    "STOR" => {
+                        control.write_all(b"150 Opening\r\n").unwrap();
+
+                        let (stream, _) = data.accept().unwrap();
+                        let mut stream = accept_tls(&config, stream);
+                        seen.resumed
+                            .push(stream.conn.handshake_kind() == Some(HandshakeKind::Resumed));
+                        io::copy(&mut stream, &mut io::sink()).unwrap();
+                        // Like some servers, leave closing it to the client
+                        held = Some(stream);
+
+                        "226 Done".to_owned()
+                    }
+                    _ => "200 Ok".to_owned(),
+                };
+
+                seen.commands.push(command);
+                control
+                    .write_all(format!("{}\r\n", reply).as_bytes())
+                    .unwrap();
+            }
+
+            drop(held);
+            seen
+        });
+
+        (addr, handle)
+    }
+
+    #[tokio::test]
+    async fn test_explicit() {
+        for version in [&TLS12, &TLS13] {
+            let (server, client) = configs(version);
+            let (addr, stand_in) = stand_in(server, false, b"hello world");
+
+            let mut client = Client::builder()
+                .probe_features(false)
+                .tls(client, "localhost")
+                .connect(addr)
+                .await
+                .unwrap();
+
+            let mut file = Vec::new();
+            client.get("a.txt", &mut file).await.unwrap();
+            assert_eq!(file, b"hello world");
+
+            drop(client);
+            let seen = stand_in.join().unwrap();
+
+            assert_eq!(
+                seen.commands,
+                [
+                    "SYST",
+                    "AUTH TLS",
+                    "PBSZ 0",
+                    "PROT P",
+                    "TYPE I",
+                    "PASV",
+                    "RETR a.txt"
+                ]
+            );
+            // Only a TLS handshake gets the data connection this far
+            assert_eq!(seen.resumed.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_implicit() {
+        let (server, client) = configs(&TLS13);
+        let (addr, stand_in) = stand_in(server, true, b"hello world");
+
+        // The stand-in only sends the greeting once the handshake is done, a
+        // client waiting for it first would time out
+        let mut client = Client::builder()
+            .probe_features(false)
+            .reply_timeout(Duration::from_secs(5))
+            .implicit_tls(client, "localhost")
+            .connect(addr)
+            .await
+            .unwrap();
+
+        assert_eq!(client.welcome().message(), "Ready");
+
+        let mut file = Vec::new();
+        client.get("a.txt", &mut file).await.unwrap();
+        assert_eq!(file, b"hello world");
+
+        drop(client);
+        let seen = stand_in.join().unwrap();
+
+        assert_eq!(
+            seen.commands,
+            ["SYST", "PBSZ 0", "PROT P", "TYPE I", "PASV", "RETR a.txt"]
+        );
+        assert_eq!(seen.resumed.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resumption() {
+        for version in [&TLS12, &TLS13] {
+            let (server, client) = configs(version);
+            let (addr, stand_in) = stand_in(server, false, b"hello world");
+
+            let mut client = Client::builder()
+                .probe_features(false)
+                .tls(client, "localhost")
+                .connect(addr)
+                .await
+                .unwrap();
+
+            // More transfers than the server sends TLS 1.3 tickets on the
+            // control connection
+            for _ in 0..3 {
+                client.get("a.txt", &mut Vec::new()).await.unwrap();
+            }
+
+            drop(client);
+            assert_eq!(stand_in.join().unwrap().resumed, [true; 3], "{:?}", version);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put() {
+        for version in [&TLS12, &TLS13] {
+            let (server, client) = configs(version);
+            let (addr, stand_in) = stand_in(server, false, b"");
+
+            let mut client = Client::builder()
+                .probe_features(false)
+                .data_idle_timeout(Duration::from_secs(10))
+                .tls(client, "localhost")
+                .connect(addr)
+                .await
+                .unwrap();
+
+            // The server leaves closing the data connection to the client,
+            // which only reads from it until there is a ticket
+            let start = Instant::now();
+
+            for _ in 0..3 {
+                client.put("a.txt", &mut &b"hello world"[..]).await.unwrap();
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", version);
+
+            drop(client);
+            assert_eq!(stand_in.join().unwrap().resumed, [true; 3], "{:?}", version);
+        }
+    }
+}
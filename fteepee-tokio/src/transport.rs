@@ -0,0 +1,285 @@
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::RangeInclusive,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpListener, TcpSocket, TcpStream, ToSocketAddrs},
+};
+
+use tracing::warn;
+
+use crate::{proxy, Proxy, Target};
+
+/// A connection to the server that commands or data are sent over.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin {
+    /// Address of the server, passive data connections are made to its IP.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Address of this end of the connection, active mode listens on its IP.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl Transport for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+}
+
+/// Opens data connections, e.g. to tunnel them or to set socket options.
+///
+/// The client applies its [`Timeouts`](crate::Timeouts) on top, connecting
+/// and accepting do not need to time out themselves.
+pub trait Connector {
+    type Stream: Transport;
+
+    /// Connects to `addr` for a passive transfer.
+    fn connect(
+        &mut self,
+        addr: SocketAddr,
+    ) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+
+    /// Starts listening for an active transfer on `ip`, using one of `ports`
+    /// if given, and returns the address the server should connect to.
+    ///
+    /// Active mode is unsupported unless this and [`Connector::accept`] are
+    /// implemented.
+    fn listen(
+        &mut self,
+        ip: IpAddr,
+        ports: Option<RangeInclusive<u16>>,
+    ) -> impl Future<Output = io::Result<SocketAddr>> + Send {
+        let _ = (ip, ports);
+        async { Err(active_unsupported()) }
+    }
+
+    /// Accepts the server's connection to the address returned by
+    /// [`Connector::listen`]. Connections from any IP other than `peer`, the
+    /// server's, should be turned away.
+    fn accept(&mut self, peer: IpAddr) -> impl Future<Output = io::Result<Self::Stream>> + Send {
+        let _ = peer;
+        async { Err(active_unsupported()) }
+    }
+}
+
+/// Opens data connections over TCP, which is what clients connected with
+/// [`ClientBuilder::connect`](crate::ClientBuilder::connect) use.
+#[derive(Debug, Default)]
+pub struct TcpConnector {
+    local_addr: Option<IpAddr>,
+    proxy: Option<Proxy>,
+    /// The server's name when the proxy resolves it, see
+    /// [`TcpConnector::connect_any`].
+    host: Option<String>,
+    listener: Option<TcpListener>,
+}
+
+impl TcpConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes passive data connections from `ip`.
+    pub fn local_addr(mut self, ip: IpAddr) -> Self {
+        self.local_addr = Some(ip);
+        self
+    }
+
+    /// Makes passive data connections through `proxy`, active mode is
+    /// unsupported as the server can't connect back through it.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connects to the first of `target`'s addresses that accepts the
+    /// connection, returning the address as well.
+    ///
+    /// With a proxy a host name is sent to it unresolved, so the address
+    /// returned is unspecified and passive data connections to that address
+    /// are made to the host name instead.
+    pub(crate) async fn connect_any(
+        &mut self,
+        target: &Target,
+    ) -> io::Result<(TcpStream, SocketAddr)> {
+        let (host, port) = match (&self.proxy, target) {
+            (Some(proxy), Target::Host(host, port)) => {
+                let stream = proxy::connect(proxy, target, self.local_addr).await?;
+                self.host = Some(host.clone());
+
+                return Ok((stream, SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), *port)));
+            }
+            (_, Target::Host(host, port)) => (host.as_str(), *port),
+            (_, Target::Addr(addr)) => return Ok((self.connect(*addr).await?, *addr)),
+        };
+
+        let mut last_err = None;
+
+        for addr in lookup_host((host, port)).await? {
+            match self.connect(addr).await {
+                Ok(stream) => return Ok((stream, addr)),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(no_addresses))
+    }
+}
+
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    async fn connect(&mut self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let proxy = match &self.proxy {
+            Some(proxy) => proxy,
+            None => return connect(addr, self.local_addr).await,
+        };
+
+        let target = match &self.host {
+            Some(host) if addr.ip().is_unspecified() => Target::Host(host.clone(), addr.port()),
+            _ => Target::Addr(addr),
+        };
+
+        proxy::connect(proxy, &target, self.local_addr).await
+    }
+
+    async fn listen(
+        &mut self,
+        ip: IpAddr,
+        ports: Option<RangeInclusive<u16>>,
+    ) -> io::Result<SocketAddr> {
+        // We would tell the server to connect to our side of the connection
+        // to the proxy, which it can't reach
+        if self.proxy.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "active mode is not supported through a proxy",
+            ));
+        }
+
+        let listener = match ports {
+            Some(ports) => bind(ip, ports).await?,
+            None => TcpListener::bind((ip, 0)).await?,
+        };
+
+        let addr = listener.local_addr()?;
+        self.listener = Some(listener);
+
+        Ok(addr)
+    }
+
+    async fn accept(&mut self, peer: IpAddr) -> io::Result<TcpStream> {
+        let listener = self.listener.take().ok_or_else(not_listening)?;
+
+        loop {
+            let (stream, addr) = listener.accept().await?;
+
+            // Anyone else could be trying to steal or inject the transfer
+            if addr.ip().to_canonical() == peer.to_canonical() {
+                return Ok(stream);
+            }
+
+            warn!("Ignoring data connection from {}, expected {}", addr, peer);
+        }
+    }
+}
+
+/// Connects to the first of `addr`'s addresses that accepts the connection,
+/// from `local` if there is one.
+pub(crate) async fn connect(
+    addr: impl ToSocketAddrs,
+    local: Option<IpAddr>,
+) -> io::Result<TcpStream> {
+    let local = match local {
+        Some(local) => local,
+        None => return TcpStream::connect(addr).await,
+    };
+
+    let mut last_err = None;
+
+    for addr in lookup_host(addr).await? {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+
+        let result = match socket.bind(SocketAddr::new(local, 0)) {
+            Ok(()) => socket.connect(addr).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(no_addresses))
+}
+
+async fn bind(ip: IpAddr, ports: RangeInclusive<u16>) -> io::Result<TcpListener> {
+    let mut last_err = None;
+
+    for port in ports {
+        match TcpListener::bind((ip, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty port range")))
+}
+
+fn no_addresses() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+}
+
+fn not_listening() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "accepting without listening")
+}
+
+fn active_unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "active mode is not supported")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use super::{connect, Connector, TcpConnector};
+
+    const SERVER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[tokio::test]
+    async fn test_accept_from_peer() {
+        let mut connector = TcpConnector::new();
+        let addr = connector.listen(SERVER, None).await.unwrap();
+
+        // Another host gets in before the server does
+        let _other = connect(addr, Some(IpAddr::from([127, 0, 0, 2])))
+            .await
+            .unwrap();
+        let server = connect(addr, Some(SERVER)).await.unwrap();
+
+        let stream = connector.accept(SERVER).await.unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), server.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_accept_without_listening() {
+        let err = TcpConnector::new().accept(SERVER).await.unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+}